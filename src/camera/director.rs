@@ -0,0 +1,272 @@
+use level::{CameraPathData, CameraZoneData, WaypointData};
+use physics::quad_tree::Rect;
+use super::{Camera, Follow, Target};
+use Vector2;
+
+/// Decides what the camera does: follows the players, keeps to the rules of
+/// the camera zones they're in, or plays a scripted path.
+///
+/// Zones and paths come from the level data, the game only reports players
+/// entering and leaving zones.
+pub struct Director {
+    pub follow: Follow,
+    zones: Vec<CameraZoneData>,
+    paths: Vec<CameraPathData>,
+    /// Zones with players in them, the latest entered last, with how many
+    /// players are in each.
+    inside: Vec<(String, usize)>,
+    played: Vec<String>,
+    script: Option<Script>,
+}
+
+impl Director {
+    pub fn new(follow: Follow, zones: Vec<CameraZoneData>, paths: Vec<CameraPathData>) -> Director {
+        Director {
+            follow,
+            zones,
+            paths,
+            inside: vec![],
+            played: vec![],
+            script: None,
+        }
+    }
+
+    /// A player entered the zone named `name`, zones without rules are
+    /// ignored.
+    pub fn enter(&mut self, name: &str, camera: &Camera) {
+        let path = match self.zones.iter().find(|z| z.name == name) {
+            Some(zone) => zone.path.clone(),
+            None => return,
+        };
+
+        match self.inside.iter().position(|&(ref n, _)| n == name) {
+            Some(ix) => self.inside[ix].1 += 1,
+            None => self.inside.push((name.to_owned(), 1)),
+        }
+
+        if let Some(path) = path {
+            if !self.played.contains(&path) {
+                self.play(&path, camera);
+            }
+        }
+    }
+
+    pub fn leave(&mut self, name: &str) {
+        if let Some(ix) = self.inside.iter().position(|&(ref n, _)| n == name) {
+            self.inside[ix].1 -= 1;
+            if self.inside[ix].1 == 0 {
+                self.inside.remove(ix);
+            }
+        }
+    }
+
+    /// Pans along the path named `name`, then goes back to following.
+    pub fn play(&mut self, name: &str, camera: &Camera) {
+        if let Some(path) = self.paths.iter().find(|p| p.name == name) {
+            self.played.push(name.to_owned());
+            self.script = Some(Script::new(path, camera));
+        }
+    }
+
+    /// Paths played so far, each plays once.
+    pub fn played(&self) -> &[String] {
+        &self.played
+    }
+
+    /// Takes over the paths another director played, so a director built
+    /// in its place doesn't play them again.
+    pub fn set_played(&mut self, played: Vec<String>) {
+        self.played = played;
+    }
+
+    pub fn is_scripted(&self) -> bool {
+        self.script.is_some()
+    }
+
+    /// The rules in force, from the zone entered last.
+    pub fn zone(&self) -> Option<&CameraZoneData> {
+        self.inside
+            .last()
+            .and_then(|&(ref name, _)| self.zones.iter().find(|z| &z.name == name))
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, target: &Target, dt: f64, bounds: &Rect) {
+        let scripted = match self.script {
+            Some(ref mut script) => script.update(dt),
+            None => None,
+        };
+        match scripted {
+            Some((at, zoom)) => {
+                camera.move_to(at);
+                camera.set_zoom(zoom);
+                return;
+            }
+            None => if self.script.take().is_some() {
+                self.follow.place(camera.location());
+            },
+        }
+
+        let (lock_x, lock_y, zoom) = match self.zone() {
+            Some(zone) => (zone.lock_x, zone.lock_y, zone.zoom.unwrap_or(1.0)),
+            None => (None, None, 1.0),
+        };
+        self.follow.lock_x = lock_x;
+        self.follow.lock_y = lock_y;
+        camera.zoom_to(zoom);
+
+        self.follow.update(camera, target, dt, bounds);
+    }
+}
+
+/// A path being played, easing from one waypoint to the next.
+struct Script {
+    waypoints: Vec<WaypointData>,
+    leg: usize,
+    time: f64,
+    from: Vector2,
+    from_zoom: f64,
+}
+
+impl Script {
+    fn new(path: &CameraPathData, camera: &Camera) -> Script {
+        Script {
+            waypoints: path.waypoints.clone(),
+            leg: 0,
+            time: 0.0,
+            from: camera.location(),
+            from_zoom: camera.zoom(),
+        }
+    }
+
+    /// Where the camera is along the path and how far it's zoomed, `None`
+    /// once it's over.
+    fn update(&mut self, dt: f64) -> Option<(Vector2, f64)> {
+        self.time += dt;
+
+        loop {
+            let w = match self.waypoints.get(self.leg) {
+                Some(w) => *w,
+                None => return None,
+            };
+            let to_zoom = w.zoom.unwrap_or(self.from_zoom);
+
+            if self.time < w.duration + w.hold {
+                let t = if w.duration > 0.0 {
+                    (self.time / w.duration).min(1.0)
+                } else {
+                    1.0
+                };
+                let s = t * t * (3.0 - 2.0 * t);
+
+                return Some((
+                    self.from + (w.position() - self.from) * s,
+                    self.from_zoom + (to_zoom - self.from_zoom) * s,
+                ));
+            }
+
+            self.time -= w.duration + w.hold;
+            self.from = w.position();
+            self.from_zoom = to_zoom;
+            self.leg += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::FollowConfig;
+
+    fn director() -> Director {
+        let zones = vec![
+            CameraZoneData {
+                name: "arena".to_owned(),
+                lock_x: Some(500.0),
+                lock_y: None,
+                zoom: Some(0.5),
+                path: Some("intro".to_owned()),
+            },
+        ];
+        let paths = vec![
+            CameraPathData {
+                name: "intro".to_owned(),
+                waypoints: vec![
+                    WaypointData {
+                        x: 100.0,
+                        y: 0.0,
+                        zoom: Some(2.0),
+                        duration: 1.0,
+                        hold: 0.5,
+                    },
+                ],
+            },
+        ];
+        Director::new(Follow::new(FollowConfig::default()), zones, paths)
+    }
+
+    fn target() -> Target {
+        Target {
+            position: Vector2::new(300.0, 250.0),
+            velocity: Vector2::new(0.0, 0.0),
+            on_ground: true,
+        }
+    }
+
+    #[test]
+    fn path_plays_once_then_zone_rules_apply() {
+        let bounds = Rect::new(-1000.0, -1000.0, 3000.0, 3000.0);
+        let mut c = Camera::new(640, 480, 200.0, 150.0);
+        let mut d = director();
+
+        d.enter("arena", &c);
+        assert!(d.is_scripted());
+
+        d.update(&mut c, &target(), 0.5, &bounds);
+        assert_eq!(c.location(), Vector2::new(50.0, 0.0));
+        assert_eq!(c.zoom(), 1.5);
+
+        d.update(&mut c, &target(), 0.75, &bounds);
+        assert_eq!(c.location(), Vector2::new(100.0, 0.0));
+
+        for _ in 0..300 {
+            d.update(&mut c, &target(), 0.016, &bounds);
+        }
+        assert!(!d.is_scripted());
+        assert!((c.location().x - 500.0).abs() < 0.5, "{:?}", c.location());
+        assert!((c.zoom() - 0.5).abs() < 0.01);
+
+        d.leave("arena");
+        d.enter("arena", &c);
+        assert!(!d.is_scripted());
+    }
+
+    #[test]
+    fn zone_lasts_while_anyone_is_inside() {
+        let c = Camera::new(640, 480, 200.0, 150.0);
+        let mut d = director();
+
+        d.enter("arena", &c);
+        d.enter("arena", &c);
+        d.leave("arena");
+        assert!(d.zone().is_some());
+
+        d.leave("arena");
+        assert!(d.zone().is_none());
+
+        d.enter("nowhere", &c);
+        assert!(d.zone().is_none());
+    }
+
+    #[test]
+    fn taken_over_paths_dont_play_again() {
+        let c = Camera::new(640, 480, 200.0, 150.0);
+        let mut old = director();
+        old.enter("arena", &c);
+
+        let mut d = director();
+        d.set_played(old.played().to_vec());
+        d.enter("arena", &c);
+        assert!(!d.is_scripted());
+        assert!(d.zone().is_some());
+    }
+}
@@ -0,0 +1,156 @@
+use Vector2;
+
+/// Feedback layered over wherever the camera is: trauma driven shake, kicks
+/// and hit-pauses.
+///
+/// Shake grows with the square of the trauma, so small knocks barely show
+/// and big ones stack up quickly, then fades as the trauma wears off. It
+/// follows smooth noise instead of jumping around at random.
+pub struct Effects {
+    /// Furthest the shake moves the picture, in pixels.
+    pub max_offset: f64,
+    /// Furthest the shake turns the picture, in radians.
+    pub max_angle: f64,
+    /// How quickly the shake wanders, in noise cells per second.
+    pub frequency: f64,
+    /// Trauma lost per second.
+    pub trauma_decay: f64,
+    /// How quickly kicks settle, in e-foldings per second.
+    pub kick_decay: f64,
+    trauma: f64,
+    time: f64,
+    /// In world directions, pixels.
+    kick: Vector2,
+    paused_ticks: u32,
+}
+
+impl Effects {
+    pub fn new() -> Effects {
+        Effects {
+            max_offset: 24.0,
+            max_angle: 0.05,
+            frequency: 15.0,
+            trauma_decay: 1.2,
+            kick_decay: 12.0,
+            trauma: 0.0,
+            time: 0.0,
+            kick: Vector2::new(0.0, 0.0),
+            paused_ticks: 0,
+        }
+    }
+
+    /// Adds to the trauma, which tops out at 1.
+    pub fn add_trauma(&mut self, amount: f64) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    pub fn trauma(&self) -> f64 {
+        self.trauma
+    }
+
+    /// Shoves the picture by `by` pixels, in world directions, from where
+    /// it settles back.
+    pub fn kick(&mut self, by: Vector2) {
+        self.kick += by;
+    }
+
+    /// Holds the gameplay for the next `ticks` ticks, the longest pause
+    /// asked for wins.
+    pub fn hit_pause(&mut self, ticks: u32) {
+        self.paused_ticks = self.paused_ticks.max(ticks);
+    }
+
+    /// Whether a gameplay tick is held for a hit-pause, counting it off.
+    pub fn pause_tick(&mut self) -> bool {
+        if self.paused_ticks > 0 {
+            self.paused_ticks -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.trauma_decay * dt).max(0.0);
+        self.kick *= (-self.kick_decay * dt).exp();
+    }
+
+    /// How far the picture is moved, in screen pixels.
+    pub fn offset(&self) -> Vector2 {
+        let shake = self.shake() * self.max_offset;
+        let t = self.time * self.frequency;
+
+        Vector2::new(
+            shake * noise(1, t) + self.kick.x,
+            shake * noise(2, t) - self.kick.y,
+        )
+    }
+
+    /// How far the picture is turned, clockwise on screen.
+    pub fn angle(&self) -> f64 {
+        self.shake() * self.max_angle * noise(3, self.time * self.frequency)
+    }
+
+    fn shake(&self) -> f64 {
+        self.trauma * self.trauma
+    }
+}
+
+/// Smooth noise in `-1..1`, the same for the same seed and time.
+fn noise(seed: u64, t: f64) -> f64 {
+    let cell = t.floor();
+    let f = t - cell;
+    let a = lattice(seed, cell as i64);
+    let b = lattice(seed, cell as i64 + 1);
+
+    a + (b - a) * f * f * (3.0 - 2.0 * f)
+}
+
+fn lattice(seed: u64, cell: i64) -> f64 {
+    let mut h = (cell as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^
+        seed.wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^= h >> 31;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 29;
+
+    (h >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shake_wears_off() {
+        let mut e = Effects::new();
+        e.add_trauma(0.8);
+        e.kick(Vector2::new(10.0, 0.0));
+
+        let mut moved = false;
+        for _ in 0..30 {
+            e.update(0.016);
+            moved |= e.offset().norm() > 1.0;
+            assert!(e.offset().norm() <= e.max_offset * 2.0 + 10.0);
+        }
+        assert!(moved);
+
+        for _ in 0..120 {
+            e.update(0.016);
+        }
+        assert_eq!(e.trauma(), 0.0);
+        assert!(e.offset().norm() < 0.01, "{:?}", e.offset());
+        assert_eq!(e.angle(), 0.0);
+    }
+
+    #[test]
+    fn hit_pause_holds_ticks() {
+        let mut e = Effects::new();
+        e.hit_pause(2);
+        e.hit_pause(1);
+
+        assert!(e.pause_tick());
+        assert!(e.pause_tick());
+        assert!(!e.pause_tick());
+    }
+}
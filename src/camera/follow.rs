@@ -0,0 +1,251 @@
+use ggez::{Context, GameError, GameResult};
+use serde_json;
+
+use std::io::Read;
+
+use physics::quad_tree::Rect;
+use super::Camera;
+use Vector2;
+
+/// How the camera trails what it follows, loaded from
+/// `resources/camera.json`. Missing fields fall back to the defaults below.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FollowConfig {
+    /// Half the size of the window the target moves in without moving the
+    /// camera.
+    pub window_x: f64,
+    pub window_y: f64,
+    /// Seconds of horizontal speed the view leads by.
+    pub look_ahead_time: f64,
+    pub max_look_ahead: f64,
+    /// How fast the lead catches up with a change of speed, per second.
+    pub look_ahead_rate: f64,
+    /// Roughly the seconds it takes the view to catch up, critically
+    /// damped. Zero follows rigidly.
+    pub smooth_time: f64,
+    /// Whether to only follow vertically once the target lands, so jumps
+    /// don't bob the view.
+    pub platform_snap: bool,
+}
+
+impl Default for FollowConfig {
+    fn default() -> Self {
+        FollowConfig {
+            window_x: 96.0,
+            window_y: 160.0,
+            look_ahead_time: 0.25,
+            max_look_ahead: 320.0,
+            look_ahead_rate: 3.0,
+            smooth_time: 0.2,
+            platform_snap: true,
+        }
+    }
+}
+
+impl FollowConfig {
+    pub fn load(ctx: &mut Context) -> GameResult<FollowConfig> {
+        let file = ctx.filesystem.open("/camera.json")?;
+        FollowConfig::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> GameResult<FollowConfig> {
+        serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken camera config: {}", e))
+        })
+    }
+}
+
+/// What the camera follows this frame.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub position: Vector2,
+    pub velocity: Vector2,
+    pub on_ground: bool,
+}
+
+/// Moves a camera after a target: the target roams a window freely, the
+/// view leads in the direction it's heading, eases after it and never shows
+/// anything outside the level.
+///
+/// See the Gamasutra article linked at the top of `camera` for the ideas.
+pub struct Follow {
+    pub config: FollowConfig,
+    /// Keeps the view centered at this x instead of following.
+    pub lock_x: Option<f64>,
+    pub lock_y: Option<f64>,
+    /// Center of the window, where the camera wants to be without the lead.
+    focus: Option<Vector2>,
+    look_ahead: f64,
+    position: Vector2,
+    velocity: Vector2,
+}
+
+impl Follow {
+    pub fn new(config: FollowConfig) -> Follow {
+        Follow {
+            config,
+            lock_x: None,
+            lock_y: None,
+            focus: None,
+            look_ahead: 0.0,
+            position: Vector2::new(0.0, 0.0),
+            velocity: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /// Jumps straight to the target on the next update, after teleports.
+    pub fn reset(&mut self) {
+        self.focus = None;
+    }
+
+    /// Eases on from `at`, after something else moved the camera.
+    pub fn place(&mut self, at: Vector2) {
+        self.focus = Some(at);
+        self.position = at;
+        self.velocity = Vector2::new(0.0, 0.0);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, target: &Target, dt: f64, bounds: &Rect) {
+        let c = &self.config;
+        let at = target.position;
+
+        let focus = match self.focus {
+            None => {
+                self.look_ahead = 0.0;
+                self.velocity = Vector2::new(0.0, 0.0);
+                self.position = clamp_view(at, camera.size(), bounds);
+                at
+            }
+            Some(focus) => {
+                let x = window(focus.x, at.x, c.window_x);
+                let y = if c.platform_snap && target.on_ground {
+                    at.y
+                } else {
+                    window(focus.y, at.y, c.window_y)
+                };
+                Vector2::new(x, y)
+            }
+        };
+        self.focus = Some(focus);
+        let focus = Vector2::new(
+            self.lock_x.unwrap_or(focus.x),
+            self.lock_y.unwrap_or(focus.y),
+        );
+
+        let lead = if self.lock_x.is_some() {
+            0.0
+        } else {
+            (target.velocity.x * c.look_ahead_time)
+                .max(-c.max_look_ahead)
+                .min(c.max_look_ahead)
+        };
+        self.look_ahead += (lead - self.look_ahead) * (c.look_ahead_rate * dt).min(1.0);
+
+        let wanted = clamp_view(
+            focus + Vector2::new(self.look_ahead, 0.0),
+            camera.size(),
+            bounds,
+        );
+        let x = smooth_damp(self.position.x, wanted.x, &mut self.velocity.x, c.smooth_time, dt);
+        let y = smooth_damp(self.position.y, wanted.y, &mut self.velocity.y, c.smooth_time, dt);
+
+        // The view can grow while easing, so check the bounds again.
+        self.position = clamp_view(Vector2::new(x, y), camera.size(), bounds);
+        camera.move_to(self.position);
+    }
+}
+
+/// Moves a window centered at `center` with half size `half` just enough to
+/// contain `at`.
+fn window(center: f64, at: f64, half: f64) -> f64 {
+    if at > center + half {
+        at - half
+    } else if at < center - half {
+        at + half
+    } else {
+        center
+    }
+}
+
+/// Keeps a view of `size` centered at `center` inside `bounds`, centering it
+/// on them when it's larger.
+fn clamp_view(center: Vector2, size: Vector2, bounds: &Rect) -> Vector2 {
+    let clamp = |c: f64, half: f64, lo: f64, len: f64| if half * 2.0 >= len {
+        lo + len / 2.0
+    } else {
+        c.max(lo + half).min(lo + len - half)
+    };
+
+    Vector2::new(
+        clamp(center.x, size.x / 2.0, bounds.x, bounds.w),
+        clamp(center.y, size.y / 2.0, bounds.y, bounds.h),
+    )
+}
+
+/// Eases `current` towards `target` like a critically damped spring,
+/// arriving in about `smooth_time` seconds without overshooting.
+fn smooth_damp(current: f64, target: f64, velocity: &mut f64, smooth_time: f64, dt: f64) -> f64 {
+    if smooth_time <= 0.0 {
+        *velocity = 0.0;
+        return target;
+    }
+
+    let omega = 2.0 / smooth_time;
+    let x = omega * dt;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+
+    let change = current - target;
+    let temp = (*velocity + omega * change) * dt;
+    *velocity = (*velocity - omega * temp) * decay;
+    target + (change + temp) * decay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> Rect {
+        Rect::new(0.0, 0.0, 1000.0, 500.0)
+    }
+
+    fn target(x: f64, y: f64) -> Target {
+        Target {
+            position: Vector2::new(x, y),
+            velocity: Vector2::new(0.0, 0.0),
+            on_ground: true,
+        }
+    }
+
+    #[test]
+    fn window_absorbs_small_moves_and_eases_after_large_ones() {
+        let mut c = Camera::new(640, 480, 200.0, 150.0);
+        let mut f = Follow::new(FollowConfig::default());
+
+        f.update(&mut c, &target(500.0, 250.0), 0.016, &bounds());
+        assert_eq!(c.location(), Vector2::new(500.0, 250.0));
+
+        f.update(&mut c, &target(550.0, 250.0), 0.016, &bounds());
+        assert_eq!(c.location(), Vector2::new(500.0, 250.0));
+
+        for _ in 0..120 {
+            f.update(&mut c, &target(700.0, 250.0), 0.016, &bounds());
+        }
+        let settled = c.location();
+        assert!((settled.x - (700.0 - f.config.window_x)).abs() < 0.5, "{:?}", settled);
+    }
+
+    #[test]
+    fn never_shows_outside_the_bounds() {
+        let mut c = Camera::new(640, 480, 200.0, 150.0);
+        let mut f = Follow::new(FollowConfig::default());
+
+        f.update(&mut c, &target(10.0, 10.0), 0.016, &bounds());
+        assert_eq!(c.location(), Vector2::new(100.0, 75.0));
+
+        let mut wide = Camera::new(640, 480, 2000.0, 1500.0);
+        f.reset();
+        f.update(&mut wide, &target(10.0, 10.0), 0.016, &bounds());
+        assert_eq!(wide.location(), Vector2::new(500.0, 250.0));
+    }
+}
@@ -0,0 +1,412 @@
+//! A camera object for ggez.
+//! Currently ggez has no actual global camera state to use,
+//! so this really just does the coordinate transforms for you.
+//!
+//! Basically it translates ggez's coordinate system with the origin
+//! at the top-left and Y increasing downward to a coordinate system
+//! with the origin at the center of the screen and Y increasing
+//! upward.
+//!
+//! Because that makes sense, darn it.
+//!
+//! Camera movements like easing and pinning are in `follow`, a great
+//! source for how such things work is this:
+//! http://www.gamasutra.com/blogs/ItayKeren/20150511/243083/Scroll_Back_The_Theory_and_Practice_of_Cameras_in_SideScrollers.php
+
+// TODO: Debug functions to draw world and camera grid!
+
+use ggez;
+use ggez::GameResult;
+use ggez::graphics;
+use ggez::graphics::DrawParam;
+use na;
+use na::{Matrix3, Vector3};
+pub use Vector2;
+
+mod director;
+mod effects;
+mod follow;
+pub use self::director::*;
+pub use self::effects::*;
+pub use self::follow::*;
+
+/// Where the view is, how far it's zoomed in and how it's turned, kept as
+/// the affine transform from world to screen coordinates.
+pub struct Camera {
+    /// Top left corner of the part of the window the camera renders to.
+    screen_offset: Vector2,
+    screen_size: Vector2,
+    /// Size of the view at zoom 1.
+    view_size: Vector2,
+    view_center: Vector2,
+    zoom: f64,
+    target_zoom: f64,
+    /// Radians counter-clockwise, the world turns the other way on screen.
+    rotation: f64,
+    /// How quickly `zoom_to` gets there, in e-foldings per second.
+    pub zoom_rate: f64,
+    /// Only touch what's drawn, never the coordinate conversions.
+    pub effects: Effects,
+}
+
+impl Camera {
+    pub fn new(screen_width: u32, screen_height: u32, view_width: f64, view_height: f64) -> Self {
+        let screen_size = Vector2::new(screen_width as f64, screen_height as f64);
+        let view_size = Vector2::new(view_width as f64, view_height as f64);
+        Camera {
+            screen_offset: na::zero(),
+            screen_size: screen_size,
+            view_size: view_size,
+            view_center: na::zero(),
+            zoom: 1.0,
+            target_zoom: 1.0,
+            rotation: 0.0,
+            zoom_rate: 6.0,
+            effects: Effects::new(),
+        }
+    }
+
+    /// Renders to a rectangle of the window instead of all of it.
+    pub fn set_viewport(&mut self, viewport: graphics::Rect) {
+        self.screen_offset = Vector2::new(viewport.x as f64, viewport.y as f64);
+        self.screen_size = Vector2::new(viewport.w as f64, viewport.h as f64);
+    }
+
+    pub fn viewport(&self) -> graphics::Rect {
+        graphics::Rect::new(
+            self.screen_offset.x as f32,
+            self.screen_offset.y as f32,
+            self.screen_size.x as f32,
+            self.screen_size.y as f32,
+        )
+    }
+
+    /// Whether a point in the window is in the viewport.
+    pub fn contains(&self, (sx, sy): (i32, i32)) -> bool {
+        let p = Vector2::new(sx as f64, sy as f64) - self.screen_offset;
+        p.x >= 0.0 && p.y >= 0.0 && p.x < self.screen_size.x && p.y < self.screen_size.y
+    }
+
+    pub fn move_by(&mut self, by: Vector2) {
+        self.view_center += by;
+    }
+
+    pub fn move_to(&mut self, to: Vector2) {
+        self.view_center = to;
+    }
+
+    /// Centers the view on `points` and grows it past `min_size` as far as
+    /// needed to keep them all `margin` away from the edges, keeping the
+    /// aspect ratio of `min_size`.
+    pub fn frame(&mut self, points: &[Vector2], margin: Vector2, min_size: Vector2) {
+        if points.is_empty() {
+            return;
+        }
+
+        let (mut lo, mut hi) = (points[0], points[0]);
+        for p in points.iter() {
+            lo = Vector2::new(lo.x.min(p.x), lo.y.min(p.y));
+            hi = Vector2::new(hi.x.max(p.x), hi.y.max(p.y));
+        }
+
+        let needed = hi - lo + margin * 2.0;
+        let fit = (needed.x / min_size.x).max(needed.y / min_size.y).max(1.0);
+
+        self.view_center = (lo + hi) / 2.0;
+        self.view_size = min_size * fit;
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Zooms right away, above 1 is closer.
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom;
+        self.target_zoom = zoom;
+    }
+
+    /// Zooms gradually over the next updates.
+    pub fn zoom_to(&mut self, zoom: f64) {
+        self.target_zoom = zoom;
+    }
+
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    pub fn rotate_to(&mut self, rotation: f64) {
+        self.rotation = rotation;
+    }
+
+    pub fn rotate_by(&mut self, by: f64) {
+        self.rotation += by;
+    }
+
+    /// Advances the zoom transition and the effects.
+    pub fn update(&mut self, dt: f64) {
+        self.effects.update(dt);
+
+        if (self.target_zoom / self.zoom - 1.0).abs() < 0.001 {
+            self.zoom = self.target_zoom;
+        } else {
+            // Evenly in log space, so zooming in and out feel the same.
+            let step = (1.0 - (-self.zoom_rate * dt).exp()).min(1.0);
+            self.zoom *= (self.target_zoom / self.zoom).powf(step);
+        }
+    }
+
+    pub fn draw_scale(&self) -> graphics::Point {
+        let scale = self.pixels_per_unit();
+        graphics::Point::new(scale.x as f32, scale.y as f32)
+    }
+
+    fn pixels_per_unit(&self) -> Vector2 {
+        self.screen_size.component_div(&self.size())
+    }
+
+    /// Maps world coordinates to screen coordinates: follow the view,
+    /// undo its turn, scale to pixels and flip Y to point down from the
+    /// top-left corner of the viewport.
+    pub fn transform(&self) -> Matrix3<f64> {
+        let ppu = self.pixels_per_unit();
+
+        translation(self.screen_offset + self.screen_size / 2.0) * scaling(Vector2::new(1.0, -1.0))
+            * scaling(ppu) * rotation(-self.rotation) * translation(-self.view_center)
+    }
+
+    /// The inverse of `transform`, undoing its steps in reverse.
+    pub fn inverse_transform(&self) -> Matrix3<f64> {
+        let upp = self.size().component_div(&self.screen_size);
+
+        translation(self.view_center) * rotation(self.rotation) * scaling(upp)
+            * scaling(Vector2::new(1.0, -1.0))
+            * translation(-self.screen_offset - self.screen_size / 2.0)
+    }
+
+    /// Where things get drawn: `transform` with the effects on top, turning
+    /// around the middle of the viewport.
+    pub fn draw_transform(&self) -> Matrix3<f64> {
+        let middle = self.screen_offset + self.screen_size / 2.0;
+
+        translation(self.effects.offset() + middle) * rotation(self.effects.angle())
+            * translation(-middle) * self.transform()
+    }
+
+    /// How far drawn things turn, clockwise on screen.
+    pub fn draw_rotation(&self) -> f64 {
+        self.rotation + self.effects.angle()
+    }
+
+    pub fn world_to_screen(&self, from: Vector2) -> Vector2 {
+        apply(&self.transform(), from)
+    }
+
+    pub fn screen_to_world(&self, from: Vector2) -> Vector2 {
+        apply(&self.inverse_transform(), from)
+    }
+
+    /// Translates a point in world-space to a point in
+    /// screen-space.
+    ///
+    /// Does not do any clipping or anything, since it does
+    /// not know how large the thing that might be drawn is;
+    /// that's not its job.
+    pub fn world_to_screen_coords(&self, from: Vector2) -> (i32, i32) {
+        let p = self.world_to_screen(from);
+        (p.x.round() as i32, p.y.round() as i32)
+    }
+
+    pub fn screen_to_world_coords(&self, from: (i32, i32)) -> Vector2 {
+        let (sx, sy) = from;
+        self.screen_to_world(Vector2::new(sx as f64, sy as f64))
+    }
+
+    pub fn location(&self) -> Vector2 {
+        self.view_center
+    }
+
+    /// The size of the view in world units, zoom included.
+    pub fn size(&self) -> Vector2 {
+        self.view_size / self.zoom
+    }
+
+    /// Where to draw something at `location`, effects included.
+    pub fn calculate_dest_point(&self, location: Vector2) -> graphics::Point {
+        let p = apply(&self.draw_transform(), location);
+        graphics::Point::new(p.x as f32, p.y as f32)
+    }
+}
+
+fn translation(by: Vector2) -> Matrix3<f64> {
+    Matrix3::new(1.0, 0.0, by.x, 0.0, 1.0, by.y, 0.0, 0.0, 1.0)
+}
+
+fn scaling(by: Vector2) -> Matrix3<f64> {
+    Matrix3::new(by.x, 0.0, 0.0, 0.0, by.y, 0.0, 0.0, 0.0, 1.0)
+}
+
+fn rotation(angle: f64) -> Matrix3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    Matrix3::new(cos, -sin, 0.0, sin, cos, 0.0, 0.0, 0.0, 1.0)
+}
+
+fn apply(m: &Matrix3<f64>, p: Vector2) -> Vector2 {
+    let p = *m * Vector3::new(p.x, p.y, 1.0);
+    Vector2::new(p.x, p.y)
+}
+
+pub trait CameraDraw
+where
+    Self: graphics::Drawable,
+{
+    fn draw_ex_camera(
+        &self,
+        camera: &Camera,
+        ctx: &mut ggez::Context,
+        p: ggez::graphics::DrawParam,
+    ) -> GameResult<()> {
+        let dest = Vector2::new(p.dest.x as f64, p.dest.y as f64);
+        let dest = camera.calculate_dest_point(dest);
+        let scale = camera.draw_scale();
+        let orig_scale = p.scale.clone();
+        let mut my_p = p;
+        my_p.dest = dest;
+        my_p.rotation += camera.draw_rotation() as f32;
+        my_p.scale = graphics::Point::new(
+            orig_scale.x * scale.x,
+            orig_scale.y * scale.y);
+        self.draw_ex(ctx, my_p)
+    }
+
+    fn draw_camera(
+        &self,
+        camera: &Camera,
+        ctx: &mut ggez::Context,
+        dest: ggez::graphics::Point,
+        rotation: f32,
+    ) -> GameResult<()> {
+        let dest = Vector2::new(dest.x as f64, dest.y as f64);
+        let dest = camera.calculate_dest_point(dest);
+        let scale = camera.draw_scale();
+        self.draw_ex(ctx, DrawParam {
+            dest,
+            scale,
+            rotation: rotation + camera.draw_rotation() as f32,
+            ..Default::default()
+        })
+    }
+}
+
+
+impl<T> CameraDraw for T
+where
+    T: graphics::Drawable,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use Vector2;
+    use super::*;
+
+    #[test]
+    fn test_coord_round_trip() {
+        let mut c = Camera::new(640, 480, 40.0, 30.0);
+        let p1 = (200, 300);
+        {
+            let p1_world = c.screen_to_world_coords(p1);
+            assert_eq!(p1_world, Vector2::new(-7.5, -3.75));
+            let p1_screen = c.world_to_screen_coords(p1_world);
+            assert_eq!(p1, p1_screen);
+        }
+
+
+        let p2 = Vector2::new(20.0, 10.0);
+        {
+            let p2_screen = c.world_to_screen_coords(p2);
+            assert_eq!(p2_screen, (640, 80));
+            let p2_world = c.screen_to_world_coords(p2_screen);
+            assert_eq!(p2_world, p2);
+        }
+
+        c.move_to(Vector2::new(5.0, 5.0));
+
+        {
+            let p1_world = c.screen_to_world_coords(p1);
+            assert_eq!(p1_world, Vector2::new(-2.5, 1.25));
+            let p1_screen = c.world_to_screen_coords(p1_world);
+            assert_eq!(p1, p1_screen);
+        }
+        {
+            let p2_screen = c.world_to_screen_coords(p2);
+            assert_eq!(p2_screen, (560, 160));
+            let p2_world = c.screen_to_world_coords(p2_screen);
+            assert_eq!(p2_world, p2);
+        }
+
+        c.set_zoom(2.0);
+
+        {
+            assert_eq!(c.size(), Vector2::new(20.0, 15.0));
+            let p2_screen = c.world_to_screen_coords(p2);
+            assert_eq!(p2_screen, (800, 80));
+            assert_eq!(c.world_to_screen_coords(c.screen_to_world_coords(p1)), p1);
+        }
+
+        c.rotate_to(::std::f64::consts::FRAC_PI_2);
+
+        {
+            // Right of the center in the world shows below it on screen.
+            let right = c.location() + Vector2::new(1.0, 0.0);
+            assert_eq!(c.world_to_screen_coords(right), (320, 272));
+            assert_eq!(c.world_to_screen_coords(c.screen_to_world_coords(p1)), p1);
+
+            let back = c.screen_to_world(c.world_to_screen(p2));
+            assert!((back - p2).norm() < 1e-9, "{:?}", back);
+        }
+    }
+
+    #[test]
+    fn viewport_offsets_the_screen() {
+        let mut c = Camera::new(640, 480, 20.0, 15.0);
+        c.set_viewport(graphics::Rect::new(320.0, 0.0, 320.0, 240.0));
+
+        assert_eq!(c.world_to_screen_coords(Vector2::new(0.0, 0.0)), (480, 120));
+        assert_eq!(c.world_to_screen_coords(Vector2::new(10.0, 7.5)), (640, 0));
+        assert_eq!(c.screen_to_world_coords((320, 240)), Vector2::new(-10.0, -7.5));
+
+        assert!(c.contains((400, 100)));
+        assert!(!c.contains((100, 100)));
+        assert!(!c.contains((400, 240)));
+    }
+
+    #[test]
+    fn zoom_to_eases_there() {
+        let mut c = Camera::new(640, 480, 40.0, 30.0);
+
+        c.zoom_to(2.0);
+        c.update(0.016);
+        assert!(c.zoom() > 1.0 && c.zoom() < 2.0);
+
+        for _ in 0..120 {
+            c.update(0.016);
+        }
+        assert_eq!(c.zoom(), 2.0);
+    }
+
+    #[test]
+    fn frame_zooms_out_to_fit() {
+        let mut c = Camera::new(640, 480, 40.0, 30.0);
+        let min_size = Vector2::new(40.0, 30.0);
+        let margin = Vector2::new(10.0, 10.0);
+
+        c.frame(&[Vector2::new(5.0, 5.0)], margin, min_size);
+        assert_eq!(c.location(), Vector2::new(5.0, 5.0));
+        assert_eq!(c.size(), min_size);
+
+        c.frame(&[Vector2::new(0.0, 0.0), Vector2::new(100.0, 0.0)], margin, min_size);
+        assert_eq!(c.location(), Vector2::new(50.0, 0.0));
+        assert_eq!(c.size(), Vector2::new(120.0, 90.0));
+    }
+}
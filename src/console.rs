@@ -0,0 +1,257 @@
+use ggez::{Context, GameResult};
+use ggez::event::Keycode;
+use ggez::graphics;
+use ggez::graphics::{Color, DrawMode, Font, Point};
+
+use std::collections::VecDeque;
+
+use debug::{Channel, CHANNELS_ALL};
+use hud::{draw_text, TextCache};
+use physics::MovementProfile;
+
+/// Something typed into the console, for the game to carry out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Moves a player, the first unless `player` says otherwise.
+    Teleport { x: f64, y: f64, player: usize },
+    /// Changes a movement profile value of every player.
+    Set { name: String, value: f64 },
+    LoadLevel(String),
+    ToggleDebug(Channel),
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+
+        match (name, args.len()) {
+            ("teleport", 2) | ("teleport", 3) => Ok(Command::Teleport {
+                x: number(args[0])?,
+                y: number(args[1])?,
+                player: match args.get(2) {
+                    Some(p) => (number(p)? as usize).max(1) - 1,
+                    None => 0,
+                },
+            }),
+            ("set", 2) => Ok(Command::Set {
+                name: args[0].to_owned(),
+                value: number(args[1])?,
+            }),
+            ("load", 2) if args[0] == "level" => Ok(Command::LoadLevel(args[1].to_owned())),
+            ("toggle", 2) if args[0] == "debug" => match Channel::from_name(args[1]) {
+                Some(channel) => Ok(Command::ToggleDebug(channel)),
+                None => Err(format!("No debug channel {}", args[1])),
+            },
+            ("help", 0) => Ok(Command::Help),
+            _ => Err(format!("Unknown command: {}", line.trim())),
+        }
+    }
+}
+
+fn number(word: &str) -> Result<f64, String> {
+    word.parse()
+        .map_err(|_| format!("Not a number: {}", word))
+}
+
+/// The profile values `set` can change.
+pub fn profile_value<'a>(profile: &'a mut MovementProfile, name: &str) -> Option<&'a mut f64> {
+    match name {
+        "gravity" => Some(&mut profile.gravity),
+        "max_falling_speed" => Some(&mut profile.max_falling_speed),
+        "jump_speed" => Some(&mut profile.jump_speed),
+        "walk_speed" => Some(&mut profile.walk_speed),
+        "walk_accel" => Some(&mut profile.walk_accel),
+        "dash_speed" => Some(&mut profile.dash_speed),
+        _ => None,
+    }
+}
+
+pub fn help() -> String {
+    let channels: Vec<&str> = CHANNELS_ALL.iter().map(|c| c.name()).collect();
+    format!(
+        "teleport <x> <y> [player]\n\
+         set <gravity|max_falling_speed|jump_speed|walk_speed|walk_accel|dash_speed> <value>\n\
+         load level <name>\n\
+         toggle debug <{}>",
+        channels.join("|")
+    )
+}
+
+/// A drop-down console over the top of the window, opened with the key
+/// left of 1. It takes all keys while open.
+///
+/// Typed commands queue up until the game takes them, key events get no
+/// context to carry them out with.
+pub struct Console {
+    open: bool,
+    input: String,
+    /// What was typed and answered, the latest last.
+    log: VecDeque<String>,
+    commands: Vec<Command>,
+    texts: TextCache,
+}
+
+impl Console {
+    const LOG_LINES: usize = 64;
+    const MARGIN: f32 = 8.0;
+    /// Part of the window height the console covers.
+    const HEIGHT: f32 = 0.4;
+
+    pub fn new() -> Console {
+        Console {
+            open: false,
+            input: String::new(),
+            log: VecDeque::new(),
+            commands: vec![],
+            texts: TextCache::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Handles a key, returning whether the console used it.
+    pub fn key_down(&mut self, keycode: Keycode, repeat: bool) -> bool {
+        if keycode == Keycode::Backquote {
+            if !repeat {
+                self.open = !self.open;
+            }
+            return true;
+        }
+        if !self.open {
+            return false;
+        }
+
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => self.submit(),
+            Keycode::Backspace => {
+                self.input.pop();
+            }
+            Keycode::Escape => self.open = false,
+            _ => if let Some(c) = printable(keycode) {
+                self.input.push(c);
+            },
+        }
+        true
+    }
+
+    /// Adds lines to the log.
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.log.len() == Console::LOG_LINES {
+                self.log.pop_front();
+            }
+            self.log.push_back(line.to_owned());
+        }
+    }
+
+    /// Commands typed since the last call.
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        ::std::mem::replace(&mut self.commands, vec![])
+    }
+
+    fn submit(&mut self) {
+        let line = ::std::mem::replace(&mut self.input, String::new());
+        if line.trim().is_empty() {
+            return;
+        }
+
+        self.print(&format!("> {}", line));
+        match Command::parse(&line) {
+            Ok(command) => self.commands.push(command),
+            Err(e) => self.print(&e),
+        }
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, font: &Font) -> GameResult<()> {
+        if !self.open {
+            return Ok(());
+        }
+
+        let w = ctx.conf.window_width as f32;
+        let h = ctx.conf.window_height as f32 * Console::HEIGHT;
+        graphics::set_color(ctx, Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Fill,
+            graphics::Rect::new(w / 2.0, h / 2.0, w, h),
+        )?;
+
+        // From the prompt at the bottom up, as far as there is room.
+        graphics::set_color(ctx, graphics::WHITE)?;
+        let mut y = h - Console::MARGIN;
+        let prompt = format!("> {}_", self.input);
+        for line in ::std::iter::once(&prompt).chain(self.log.iter().rev()) {
+            let text = self.texts.get(ctx, font, line)?;
+            y -= text.height() as f32;
+            if y < 0.0 {
+                break;
+            }
+            draw_text(ctx, text, Point::new(Console::MARGIN, y))?;
+        }
+        self.texts.sweep();
+
+        Ok(())
+    }
+}
+
+/// The character a key types in lower case, keys for printable characters
+/// are their ASCII codes.
+fn printable(keycode: Keycode) -> Option<char> {
+    let code = keycode as i32;
+    if code >= 0x20 && code < 0x7f {
+        Some(code as u8 as char)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("teleport 10 -20.5"),
+            Ok(Command::Teleport {
+                x: 10.0,
+                y: -20.5,
+                player: 0,
+            })
+        );
+        assert_eq!(
+            Command::parse(" set  gravity -3000 "),
+            Ok(Command::Set {
+                name: "gravity".to_owned(),
+                value: -3000.0,
+            })
+        );
+        assert_eq!(
+            Command::parse("toggle debug aabb"),
+            Ok(Command::ToggleDebug(Channel::Aabbs))
+        );
+        assert_eq!(
+            Command::parse("load level graveyard"),
+            Ok(Command::LoadLevel("graveyard".to_owned()))
+        );
+        assert!(Command::parse("teleport here").is_err());
+        assert!(Command::parse("toggle debug everything").is_err());
+    }
+
+    #[test]
+    fn typing_queues_commands() {
+        let mut console = Console::new();
+        assert!(!console.key_down(Keycode::H, false));
+
+        console.key_down(Keycode::Backquote, false);
+        for &k in [Keycode::H, Keycode::E, Keycode::L, Keycode::P, Keycode::Return].iter() {
+            assert!(console.key_down(k, false));
+        }
+        assert_eq!(console.take_commands(), vec![Command::Help]);
+    }
+}
@@ -1,155 +1,463 @@
-use ggez::Context;
+use ggez::{Context, GameError, GameResult};
 use ggez::event::*;
 use ggez::graphics;
-use ggez::graphics::DrawMode;
+use ggez::graphics::{Color, DrawMode, Point};
+use serde_json;
+
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use super::camera::*;
+use super::console::Console;
+use super::input::AxisCurve;
 use super::level::*;
 use super::player::*;
 use super::physics::*;
+use super::physics::invariants;
+use super::physics::world::World;
+use super::state::{Controlled, Regions, StateMachine};
+
+/// Which debug channels are on, a bit per `Channel`. Global so the headless
+/// simulation can log without knowing about the game.
+static CHANNELS: AtomicUsize = AtomicUsize::new(0);
+
+/// A kind of debug output, switched on and off independently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Channel {
+    /// The collision grid.
+    Tiles,
+    /// Player bounding boxes, the box swept on the last step and the probes
+    /// picking up resting contacts.
+    Aabbs,
+    Velocities,
+    /// The state stacks of every region, a square per state.
+    States,
+    /// The tile a grabbing player holds on to.
+    Ledges,
+    /// How the trigger lookup splits the level.
+    QuadTree,
+    /// Frames per second and a graph of the frame times in the HUD.
+    Timing,
+    /// State, velocity and contacts of every player in the HUD.
+    Players,
+    Triggers,
+    /// Diagnostics, state warnings, trigger events and gamepad input in the
+    /// console.
+    Log,
+}
+
+pub const CHANNELS_ALL: [Channel; 10] = [
+    Channel::Tiles,
+    Channel::Aabbs,
+    Channel::Velocities,
+    Channel::States,
+    Channel::Ledges,
+    Channel::QuadTree,
+    Channel::Timing,
+    Channel::Players,
+    Channel::Triggers,
+    Channel::Log,
+];
+
+impl Channel {
+    fn bit(&self) -> usize {
+        1 << (*self as usize)
+    }
+
+    /// What the console calls the channel.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Channel::Tiles => "tiles",
+            Channel::Aabbs => "aabb",
+            Channel::Velocities => "velocity",
+            Channel::States => "states",
+            Channel::Ledges => "ledges",
+            Channel::QuadTree => "quadtree",
+            Channel::Timing => "timing",
+            Channel::Players => "players",
+            Channel::Triggers => "triggers",
+            Channel::Log => "log",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Channel> {
+        CHANNELS_ALL.iter().find(|c| c.name() == name).cloned()
+    }
+
+    /// The key switching the channel, the rest are set from the config or
+    /// the console.
+    pub fn hotkey(&self) -> Option<Keycode> {
+        match *self {
+            Channel::Tiles => Some(Keycode::F6),
+            Channel::Aabbs => Some(Keycode::F7),
+            Channel::Velocities => Some(Keycode::F8),
+            Channel::States => Some(Keycode::F9),
+            Channel::Ledges => Some(Keycode::F10),
+            Channel::QuadTree => Some(Keycode::F11),
+            Channel::Timing => Some(Keycode::F12),
+            Channel::Players | Channel::Triggers | Channel::Log => None,
+        }
+    }
+}
+
+/// Channels on at startup, from `resources/debug.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    pub channels: Vec<Channel>,
+}
+
+impl DebugConfig {
+    pub fn load(ctx: &mut Context) -> GameResult<DebugConfig> {
+        let file = ctx.filesystem.open("/debug.json")?;
+        DebugConfig::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> GameResult<DebugConfig> {
+        serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken debug config: {}", e))
+        })
+    }
+}
 
 pub struct Debug;
 
 impl Debug {
-    const DEBUG: bool = false;
+    pub fn is_on(channel: Channel) -> bool {
+        CHANNELS.load(Ordering::Relaxed) & channel.bit() != 0
+    }
+
+    pub fn set(channel: Channel, on: bool) {
+        if on {
+            CHANNELS.fetch_or(channel.bit(), Ordering::Relaxed);
+        } else {
+            CHANNELS.fetch_and(!channel.bit(), Ordering::Relaxed);
+        }
+    }
+
+    /// Flips the channel, returning whether it's on now.
+    pub fn toggle(channel: Channel) -> bool {
+        let on = !Debug::is_on(channel);
+        Debug::set(channel, on);
+        on
+    }
+
+    /// Switches on the channels in the config, and only those.
+    pub fn configure(config: &DebugConfig) {
+        CHANNELS.store(0, Ordering::Relaxed);
+        for &channel in config.channels.iter() {
+            Debug::set(channel, true);
+        }
+    }
+
+    /// Configures the channels from `resources/debug.json`, all off when
+    /// it's missing or broken.
+    pub fn load(ctx: &mut Context, console: &mut Console) {
+        let config = DebugConfig::load(ctx).unwrap_or_else(|e| {
+            console.print(&format!("Debug channels off: {}", e));
+            DebugConfig::default()
+        });
+        Debug::configure(&config);
+    }
+
+    /// Toggles the channel on `keycode`, if any, returning whether it did.
+    pub fn hotkey(keycode: Keycode, console: &mut Console) -> bool {
+        match CHANNELS_ALL.iter().find(|c| c.hotkey() == Some(keycode)) {
+            Some(&channel) => {
+                let on = Debug::toggle(channel);
+                console.print(&format!("{} {}", channel.name(), if on { "on" } else { "off" }));
+                true
+            }
+            None => false,
+        }
+    }
 
     pub fn draw_level_obstacles(ctx: &mut Context, terrain: &Terrain, camera: &Camera) {
-        if Debug::DEBUG {
-            for y in 0..terrain.height {
-                for x in 0..terrain.width {
-                    if terrain.get_tile(x as isize, y as isize) == TileType::Block {
-                        let pos = terrain.get_map_tile_position(x as isize, y as isize);
-
-                        let dest = camera.calculate_dest_point(pos);
-                        let x = dest.x as f32;
-                        let y = dest.y as f32;
-                        let w = camera.draw_scale().x * terrain.tile_size as f32;
-                        let h = camera.draw_scale().y * terrain.tile_size as f32;
-
-                        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 0.03))
-                            .unwrap();
-                        graphics::rectangle(ctx, DrawMode::Fill, graphics::Rect::new(x, y, w, h))
-                            .unwrap();
-                        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 0.7)).unwrap();
-                        graphics::rectangle(ctx, DrawMode::Line, graphics::Rect::new(x, y, w, h))
-                            .unwrap();
-                        graphics::set_color(ctx, graphics::WHITE).unwrap();
-                    }
-                }
+        if !Debug::is_on(Channel::Tiles) {
+            return;
+        }
+
+        let w = camera.draw_scale().x * terrain.tile_size as f32;
+        let h = camera.draw_scale().y * terrain.tile_size as f32;
+
+        for y in 0..terrain.height {
+            for x in 0..terrain.width {
+                let (fill, line) = match terrain.get_tile(x as isize, y as isize) {
+                    TileType::Block => (
+                        Color::new(1.0, 1.0, 1.0, 0.03),
+                        Color::new(1.0, 1.0, 1.0, 0.7),
+                    ),
+                    TileType::OneWay => (
+                        Color::new(1.0, 0.8, 0.3, 0.03),
+                        Color::new(1.0, 0.8, 0.3, 0.7),
+                    ),
+                    TileType::Empty => continue,
+                };
+                let pos = terrain.get_map_tile_position(x as isize, y as isize);
+                let dest = camera.calculate_dest_point(pos);
+                let rect = graphics::Rect::new(dest.x, dest.y, w, h);
+
+                graphics::set_color(ctx, fill).unwrap();
+                graphics::rectangle(ctx, DrawMode::Fill, rect).unwrap();
+                graphics::set_color(ctx, line).unwrap();
+                graphics::rectangle(ctx, DrawMode::Line, rect).unwrap();
             }
-        };
+        }
+        graphics::set_color(ctx, graphics::WHITE).unwrap();
     }
 
-    pub fn draw_aabb(ctx: &mut Context, player: &Player, camera: &Camera) {
-        if true {
-            // if Debug::DEBUG {
-            let dd = camera.calculate_dest_point(player.mv.position + player.mv.aabb.offset);
-            let scale = camera.draw_scale();
-            let rect = graphics::Rect::new(
-                dd.x,
-                dd.y,
-                player.mv.aabb.half_size.x as f32 * 2.0 * scale.x,
-                player.mv.aabb.half_size.y as f32 * 2.0 * scale.y,
-            );
+    pub fn draw_triggers(ctx: &mut Context, world: &World, camera: &Camera) {
+        if !Debug::is_on(Channel::Triggers) {
+            return;
+        }
 
-            let position = &player.mv.position;
-
-            let bottom = {
-                let s = player
-                    .mv
-                    .aabb
-                    .sensor(position, Sensor::BottomLeft)
-                    .right()
-                    .down()
-                    .ok();
-                vec![
-                    Vector2::new(s.x + player.mv.aabb.half_size.x * 2.0, s.y)
-                        .left()
-                        .left(),
-                    s,
-                ]
-            };
-
-            let top = {
-                let s = player
-                    .mv
-                    .aabb
-                    .sensor(position, Sensor::TopRight)
-                    .left()
-                    .up()
-                    .ok();
-                vec![
-                    Vector2::new(s.x - player.mv.aabb.half_size.x * 2.0, s.y)
-                        .right()
-                        .right(),
-                    s,
-                ]
-            };
-
-            let sensors = vec![
-                top,
-                bottom,
-                vec![
-                    player.mv.aabb.sensor(position, Sensor::TopLeft).left().ok(),
-                    player
-                        .mv
-                        .aabb
-                        .sensor(position, Sensor::BottomLeft)
-                        .left()
-                        .ok(),
-                ],
-                vec![
-                    player
-                        .mv
-                        .aabb
-                        .sensor(position, Sensor::TopRight)
-                        .right()
-                        .ok(),
-                    player
-                        .mv
-                        .aabb
-                        .sensor(position, Sensor::BottomRight)
-                        .right()
-                        .ok(),
+        graphics::set_color(ctx, Color::new(0.3, 1.0, 0.3, 0.7)).unwrap();
+        for &(_, ref trigger) in world.triggers().iter() {
+            Debug::outline(ctx, camera, trigger.center, trigger.half_size);
+        }
+        graphics::set_color(ctx, graphics::WHITE).unwrap();
+    }
+
+    pub fn draw_quad_tree(ctx: &mut Context, world: &World, camera: &Camera) {
+        if !Debug::is_on(Channel::QuadTree) {
+            return;
+        }
+
+        graphics::set_color(ctx, Color::new(1.0, 0.3, 1.0, 0.5)).unwrap();
+        for rect in world.trigger_nodes().iter() {
+            let half_size = Vector2::new(rect.w / 2.0, rect.h / 2.0);
+            Debug::outline(ctx, camera, Vector2::new(rect.x, rect.y) + half_size, half_size);
+        }
+        graphics::set_color(ctx, graphics::WHITE).unwrap();
+    }
+
+    /// Everything the channels have to show about a player.
+    pub fn draw_player(
+        ctx: &mut Context,
+        player: &Player,
+        sm: &Regions<Player>,
+        terrain: &Terrain,
+        camera: &Camera,
+    ) {
+        if Debug::is_on(Channel::Aabbs) {
+            Debug::draw_aabb(ctx, player, camera);
+        }
+
+        let center = player.mv.position + player.mv.aabb.offset;
+
+        if Debug::is_on(Channel::Velocities) {
+            let to = center + player.mv.velocity * 0.1;
+            graphics::set_color(ctx, Color::new(1.0, 1.0, 0.3, 1.0)).unwrap();
+            graphics::line(
+                ctx,
+                &[
+                    camera.calculate_dest_point(center),
+                    camera.calculate_dest_point(to),
                 ],
-            ];
+            ).unwrap();
+        }
 
-            graphics::set_color(ctx, graphics::Color::new(0.3, 0.3, 1.0, 1.0)).unwrap();
-            graphics::rectangle(ctx, graphics::DrawMode::Line, rect).unwrap();
+        if Debug::is_on(Channel::States) {
+            // A row per region above the head, a square per stacked state,
+            // the current one solid.
+            let head = center + Vector2::new(0.0, player.mv.aabb.half_size.y + 16.0);
+            let size = 10.0;
+            for (row, machine) in sm.regions().iter().enumerate() {
+                let depth = machine.depth();
+                for ix in 0..depth {
+                    let at = head + Vector2::new(
+                        (ix as f64 - (depth - 1) as f64 / 2.0) * size * 1.5,
+                        row as f64 * size * 1.5,
+                    );
+                    let dest = camera.calculate_dest_point(at);
+                    let mode = if ix + 1 == depth {
+                        DrawMode::Fill
+                    } else {
+                        DrawMode::Line
+                    };
+                    let s = camera.draw_scale().x * size as f32;
+                    graphics::set_color(ctx, Color::new(0.3, 0.8, 1.0, 1.0)).unwrap();
+                    graphics::rectangle(ctx, mode, graphics::Rect::new(dest.x, dest.y, s, s))
+                        .unwrap();
+                }
+            }
+        }
+
+        if Debug::is_on(Channel::Ledges) && sm.is_active(PlayerState::LedgeGrab) {
+            let (x, y) = player.lg.ledge_tile;
+            let half = terrain.tile_size / 2.0;
+            graphics::set_color(ctx, Color::new(1.0, 0.5, 0.0, 1.0)).unwrap();
+            Debug::outline(
+                ctx,
+                camera,
+                terrain.get_map_tile_position(x, y),
+                Vector2::new(half, half),
+            );
+        }
+
+        graphics::set_color(ctx, graphics::WHITE).unwrap();
+    }
 
-            graphics::set_color(ctx, graphics::Color::new(1.0, 0.3, 0.3, 1.0)).unwrap();
+    fn draw_aabb(ctx: &mut Context, player: &Player, camera: &Camera) {
+        let mv = &player.mv;
+        let hs = mv.aabb.half_size;
+        let from = mv.old_position + mv.aabb.offset;
+        let to = mv.position + mv.aabb.offset;
 
-            for x in sensors.iter() {
-                let points: Vec<graphics::Point> = x.iter()
-                    .map(|v| camera.calculate_dest_point(v.clone()))
-                    .collect();
+        // Everything the box swept through on the last step.
+        let lo = Vector2::new(from.x.min(to.x), from.y.min(to.y)) - hs;
+        let hi = Vector2::new(from.x.max(to.x), from.y.max(to.y)) + hs;
+        graphics::set_color(ctx, Color::new(0.3, 0.3, 1.0, 0.4)).unwrap();
+        Debug::outline(ctx, camera, (lo + hi) / 2.0, (hi - lo) / 2.0);
 
-                graphics::line(ctx, points.as_slice()).unwrap();
+        graphics::set_color(ctx, Color::new(0.3, 0.3, 1.0, 1.0)).unwrap();
+        Debug::outline(ctx, camera, to, hs);
+
+        // What `probe_contacts` looks into for resting contacts.
+        let probes = mv.probes();
+        graphics::set_color(ctx, Color::new(1.0, 0.3, 0.3, 1.0)).unwrap();
+        let boxes = [probes.ground, probes.ceiling, probes.left, probes.right];
+        for &(center, half_size) in boxes.iter() {
+            Debug::outline(ctx, camera, center, half_size);
+        }
+        graphics::set_color(ctx, graphics::WHITE).unwrap();
+    }
+
+    /// The outline of a box in the world, turning along with the camera.
+    fn outline(ctx: &mut Context, camera: &Camera, center: Vector2, half_size: Vector2) {
+        let corners = [
+            Vector2::new(-half_size.x, -half_size.y),
+            Vector2::new(half_size.x, -half_size.y),
+            Vector2::new(half_size.x, half_size.y),
+            Vector2::new(-half_size.x, half_size.y),
+            Vector2::new(-half_size.x, -half_size.y),
+        ];
+        let points: Vec<Point> = corners
+            .iter()
+            .map(|&c| camera.calculate_dest_point(center + c))
+            .collect();
+
+        graphics::line(ctx, points.as_slice()).unwrap();
+    }
+
+    pub fn physics_diagnostics(console: &mut Console, violations: &[invariants::Violation]) {
+        if Debug::is_on(Channel::Log) {
+            for v in violations.iter() {
+                console.print(&format!("PHYSICS: {:?}", v));
             }
-            graphics::set_color(ctx, graphics::WHITE).unwrap();
-        };
+        }
+    }
+
+    pub fn trigger_event(
+        console: &mut Console,
+        slot: usize,
+        name: &str,
+        kind: &TriggerKind,
+        contact: Contact,
+    ) {
+        if Debug::is_on(Channel::Log) {
+            console.print(&format!("TRIGGER: P{} {:?} {:?} {}", slot + 1, contact, kind, name));
+        }
     }
 
-    pub fn detect_teleporting(mv: &MovingObject) {
-        if Debug::DEBUG {
-            if (mv.old_position.x - mv.position.x).abs() > 30.0 {
-                println!("X TELEPORTING: {:?}", mv);
+    /// Reports stacks that got too deep along with how they got there.
+    pub fn state_machine<C: Controlled>(console: &mut Console, sm: &mut StateMachine<C>) {
+        let warnings = sm.take_depth_warnings();
+        if Debug::is_on(Channel::Log) && !warnings.is_empty() {
+            for w in warnings.iter() {
+                console.print(&format!("STATE: stack too deep at {:.3}: {:?}", w.time, w.stack));
             }
-            if (mv.old_position.y - mv.position.y).abs() > 30.0 {
-                println!("Y TELEPORTING: {:?}", mv);
+            if sm.dropped_depth_warnings() > 0 {
+                console.print(&format!(
+                    "STATE: {} warnings dropped",
+                    sm.dropped_depth_warnings()
+                ));
             }
+            console.print(&sm.dump());
         }
     }
 
-    pub fn gamepad_axis(axis: Axis, value: i16) {
-        if Debug::DEBUG {
-            println!("{:?}: {}", axis, value);
+    /// Logs a stick only when it leaves the dead zone, goes back into it or
+    /// turns over, sticks send a flood of small moves.
+    pub fn gamepad_axis(
+        console: &mut Console,
+        sides: &mut AxisSides,
+        axis: Axis,
+        value: i16,
+        instance: i32,
+    ) {
+        let changed = sides.update(instance, axis, value);
+        if Debug::is_on(Channel::Log) && changed {
+            console.print(&format!("INSTANCE: {}; {:?}: {}", instance, axis, value));
         }
     }
 
-    pub fn gamepad_button(btn: Button, instance: i32) {
-        if Debug::DEBUG {
-            println!("INSTANCE: {}; Button: {:?}", instance, btn);
+    pub fn gamepad_button(console: &mut Console, btn: Button, instance: i32) {
+        if Debug::is_on(Channel::Log) {
+            console.print(&format!("INSTANCE: {}; Button: {:?}", instance, btn));
+        }
+    }
+}
+
+/// Which side of the dead zone every gamepad axis was on last, -1, 0 or 1.
+pub struct AxisSides {
+    sides: Vec<(i32, Axis, i8)>,
+}
+
+impl AxisSides {
+    pub fn new() -> AxisSides {
+        AxisSides { sides: vec![] }
+    }
+
+    /// Takes the axis' new value, returning whether it changed sides.
+    fn update(&mut self, instance: i32, axis: Axis, value: i16) -> bool {
+        let position = value as f64 / i16::max_value() as f64;
+        let side = if position.abs() <= AxisCurve::default().deadzone {
+            0
+        } else if position > 0.0 {
+            1
+        } else {
+            -1
+        };
+
+        match self.sides
+            .iter()
+            .position(|&(i, a, _)| i == instance && a == axis)
+        {
+            Some(ix) => {
+                let changed = self.sides[ix].2 != side;
+                self.sides[ix].2 = side;
+                changed
+            }
+            None => {
+                self.sides.push((instance, axis, side));
+                side != 0
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axes_change_sides_at_the_dead_zone() {
+        let mut sides = AxisSides::new();
+        let changes: Vec<bool> = [1000, 20000, 25000, -20000, -100]
+            .iter()
+            .map(|&v| sides.update(0, Axis::LeftX, v))
+            .collect();
+        assert_eq!(changes, vec![false, true, false, true, true]);
+        assert!(sides.update(1, Axis::LeftX, 20000));
+        assert!(!sides.update(0, Axis::LeftY, 0));
+    }
+
+    #[test]
+    fn config_names_channels() {
+        let config = DebugConfig::from_reader(r#"{"channels": ["Aabbs", "QuadTree"]}"#.as_bytes())
+            .unwrap();
+        assert_eq!(config.channels, vec![Channel::Aabbs, Channel::QuadTree]);
+        assert_eq!(Channel::from_name("aabb"), Some(Channel::Aabbs));
+    }
+}
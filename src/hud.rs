@@ -0,0 +1,233 @@
+use ggez::{Context, GameResult};
+use ggez::graphics;
+use ggez::graphics::{Color, DrawMode, Font, Point, Text};
+
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+
+use debug::{Channel, Debug};
+use party::{Party, Slot};
+
+/// Text over the views, in window pixels: frame rate and timing, and what
+/// every player is up to, each behind its debug channel.
+pub struct Hud {
+    font: Font,
+    texts: TextCache,
+}
+
+impl Hud {
+    const MARGIN: f32 = 8.0;
+    /// A frame at 60 fps, the timing graph marks it.
+    const FRAME_BUDGET: f64 = 1.0 / 60.0;
+    /// Graph pixels per millisecond.
+    const GRAPH_SCALE: f32 = 4.0;
+    const BAR_WIDTH: f32 = 3.0;
+
+    pub fn new() -> GameResult<Hud> {
+        Ok(Hud {
+            font: Font::default_font()?,
+            texts: TextCache::new(),
+        })
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        times: &FrameTimes,
+        party: &Party,
+    ) -> GameResult<()> {
+        let mut y = Hud::MARGIN;
+
+        if Debug::is_on(Channel::Timing) {
+            let line = format!(
+                "{:.0} fps, {:.1} ms",
+                times.fps(),
+                times.average() * 1000.0
+            );
+            let text = self.texts.get(ctx, &self.font, &line)?;
+            y += draw_text(ctx, text, Point::new(Hud::MARGIN, y))?;
+            Hud::draw_graph(ctx, times)?;
+        }
+
+        if Debug::is_on(Channel::Players) {
+            for (ix, slot) in party.slots.iter().enumerate() {
+                let line = describe(ix, slot);
+                let text = self.texts.get(ctx, &self.font, &line)?;
+                y += draw_text(ctx, text, Point::new(Hud::MARGIN, y))?;
+            }
+        }
+        self.texts.sweep();
+
+        graphics::set_color(ctx, graphics::WHITE)
+    }
+
+    /// Bars of the last frame times along the bottom of the window, red
+    /// where they went over budget.
+    fn draw_graph(ctx: &mut Context, times: &FrameTimes) -> GameResult<()> {
+        let bottom = ctx.conf.window_height as f32 - Hud::MARGIN;
+        let w = Hud::BAR_WIDTH;
+
+        for (ix, &dt) in times.samples().iter().enumerate() {
+            let h = (dt * 1000.0) as f32 * Hud::GRAPH_SCALE;
+            let color = if dt > Hud::FRAME_BUDGET {
+                Color::new(1.0, 0.3, 0.3, 0.8)
+            } else {
+                Color::new(0.3, 1.0, 0.3, 0.8)
+            };
+            let x = Hud::MARGIN + ix as f32 * w + w / 2.0;
+            graphics::set_color(ctx, color)?;
+            graphics::rectangle(
+                ctx,
+                DrawMode::Fill,
+                graphics::Rect::new(x, bottom - h / 2.0, w - 1.0, h),
+            )?;
+        }
+
+        let budget = bottom - (Hud::FRAME_BUDGET * 1000.0) as f32 * Hud::GRAPH_SCALE;
+        let right = Hud::MARGIN + times.capacity() as f32 * w;
+        graphics::set_color(ctx, Color::new(1.0, 1.0, 1.0, 0.6))?;
+        graphics::line(
+            ctx,
+            &[Point::new(Hud::MARGIN, budget), Point::new(right, budget)],
+        )
+    }
+}
+
+/// How long the last frames took, for the timing channel.
+pub struct FrameTimes {
+    /// Seconds, the latest last.
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl FrameTimes {
+    pub fn new(capacity: usize) -> FrameTimes {
+        FrameTimes {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, dt: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(dt);
+    }
+
+    pub fn samples(&self) -> &VecDeque<f64> {
+        &self.samples
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+
+    pub fn fps(&self) -> f64 {
+        let average = self.average();
+        if average > 0.0 { 1.0 / average } else { 0.0 }
+    }
+}
+
+/// Lines rendered in earlier frames, so a line only gets rendered again
+/// once it changes. Lines not asked for since the last `sweep` go then.
+pub struct TextCache {
+    /// Each text with whether it was asked for since the last sweep.
+    texts: HashMap<String, (Text, bool)>,
+}
+
+impl TextCache {
+    pub fn new() -> TextCache {
+        TextCache {
+            texts: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, ctx: &mut Context, font: &Font, line: &str) -> GameResult<&Text> {
+        let entry = match self.texts.entry(line.to_owned()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert((Text::new(ctx, line, font)?, false)),
+        };
+        entry.1 = true;
+        Ok(&entry.0)
+    }
+
+    /// Drops the lines that weren't asked for since the last call.
+    pub fn sweep(&mut self) {
+        self.texts.retain(|_, entry| {
+            let used = entry.1;
+            entry.1 = false;
+            used
+        });
+    }
+}
+
+/// Draws `text` with its top left corner at `at` in the current colour,
+/// returning how tall it was.
+pub fn draw_text(ctx: &mut Context, text: &Text, at: Point) -> GameResult<f32> {
+    let (w, h) = (text.width() as f32, text.height() as f32);
+    graphics::draw(ctx, text, Point::new(at.x + w / 2.0, at.y + h / 2.0), 0.0)?;
+    Ok(h)
+}
+
+/// A line about a player: its states, one per region, velocity and what
+/// it touches.
+fn describe(ix: usize, slot: &Slot) -> String {
+    let states: Vec<String> = slot.sm
+        .current()
+        .iter()
+        .map(|s| match *s {
+            Some(id) => format!("{:?}", id),
+            None => "-".to_owned(),
+        })
+        .collect();
+
+    let mv = &slot.player.mv;
+    let flags: Vec<&str> = [
+        (mv.on_ground, "on_ground"),
+        (mv.on_platform, "on_platform"),
+        (mv.pushes_left_wall, "pushes_left_wall"),
+        (mv.pushes_right_wall, "pushes_right_wall"),
+        (mv.at_ceiling, "at_ceiling"),
+    ].iter()
+        .filter(|&&(on, _)| on)
+        .map(|&(_, name)| name)
+        .collect();
+
+    format!(
+        "P{} {}  v ({:.0}, {:.0})  {}",
+        ix + 1,
+        states.join("/"),
+        mv.velocity.x,
+        mv.velocity.y,
+        flags.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_times_keep_the_latest() {
+        let mut times = FrameTimes::new(3);
+        for &dt in [0.5, 0.01, 0.02, 0.03].iter() {
+            times.push(dt);
+        }
+
+        assert_eq!(times.samples().len(), 3);
+        assert!((times.average() - 0.02).abs() < 1e-9);
+        assert!((times.fps() - 50.0).abs() < 1e-6);
+    }
+}
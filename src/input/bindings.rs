@@ -0,0 +1,118 @@
+use ggez::{Context, GameError, GameResult};
+use serde_json;
+
+use std::io::Read;
+
+use super::*;
+
+/// Which sources trigger which actions, plus how sticks are read.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    /// Picks the files: the defaults ship as `resources/input/<name>.json`,
+    /// rebinding saves `bindings-<name>.json` in the user directory.
+    pub name: String,
+    pub curve: AxisCurve,
+    bound: Vec<(Action, Source)>,
+}
+
+/// Bindings as stored on disk, sources by name.
+#[derive(Debug, Serialize, Deserialize)]
+struct BindingsFile {
+    #[serde(default)]
+    curve: AxisCurve,
+    bindings: Vec<BindingData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BindingData {
+    action: Action,
+    inputs: Vec<String>,
+}
+
+impl Bindings {
+    pub fn new(name: &str, curve: AxisCurve) -> Bindings {
+        Bindings {
+            name: name.to_owned(),
+            curve,
+            bound: vec![],
+        }
+    }
+
+    /// The player's own bindings if there are any, the defaults otherwise.
+    pub fn load(ctx: &mut Context, name: &str) -> GameResult<Bindings> {
+        match ctx.filesystem.open(Bindings::user_path(name)) {
+            Ok(file) => Bindings::from_reader(file, name),
+            Err(_) => {
+                let file = ctx.filesystem.open(format!("/input/{}.json", name))?;
+                Bindings::from_reader(file, name)
+            }
+        }
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        let file = ctx.filesystem.create(Bindings::user_path(&self.name))?;
+
+        serde_json::to_writer_pretty(file, &self.to_file()).map_err(|e| {
+            GameError::ResourceLoadError(format!("Can't save bindings {}: {}", self.name, e))
+        })
+    }
+
+    fn user_path(name: &str) -> String {
+        format!("/bindings-{}.json", name)
+    }
+
+    pub fn from_reader<R: Read>(reader: R, name: &str) -> GameResult<Bindings> {
+        let file: BindingsFile = serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken bindings {}: {}", name, e))
+        })?;
+
+        let mut bindings = Bindings::new(name, file.curve);
+        for b in file.bindings.iter() {
+            for input in b.inputs.iter() {
+                match Source::parse(input) {
+                    Some(source) => bindings.bound.push((b.action, source)),
+                    None => {
+                        return Err(GameError::ResourceLoadError(
+                            format!("Unknown input {} in bindings {}", input, name),
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn is_bound(&self, action: Action, source: Source) -> bool {
+        self.bound.iter().any(|&b| b == (action, source))
+    }
+
+    /// Sources bound to an action.
+    pub fn sources(&self, action: Action) -> Vec<Source> {
+        self.bound
+            .iter()
+            .filter(|&&(a, _)| a == action)
+            .map(|&(_, s)| s)
+            .collect()
+    }
+
+    /// Binds `source` to `action` alone, replacing whatever the action had
+    /// bound from the same kind of input.
+    pub fn bind(&mut self, action: Action, source: Source) {
+        self.bound
+            .retain(|&(a, s)| s != source && !(a == action && s.same_kind(&source)));
+        self.bound.push((action, source));
+    }
+
+    fn to_file(&self) -> BindingsFile {
+        BindingsFile {
+            curve: self.curve,
+            bindings: ACTIONS
+                .iter()
+                .map(|&action| BindingData {
+                    action,
+                    inputs: self.sources(action).iter().map(|s| s.name()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use ggez::event::{Axis, Button, Keycode};
+
+mod bindings;
+pub use self::bindings::*;
+
+/// What the game wants to know about, independent of the device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    Slide,
+    Attack,
+}
+
+pub const ACTIONS: [Action; 7] = [
+    Action::Left,
+    Action::Right,
+    Action::Up,
+    Action::Down,
+    Action::Jump,
+    Action::Slide,
+    Action::Attack,
+];
+
+impl Action {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Which way an axis has to be pushed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sign {
+    Positive,
+    Negative,
+}
+
+/// A physical input that can be bound to an action.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Source {
+    Key(Keycode),
+    Button(Button),
+    Axis(Axis, Sign),
+}
+
+impl Source {
+    /// Parses `key:<name>`, `button:<name>` and `axis:<name>+` or
+    /// `axis:<name>-`, using SDL's names.
+    pub fn parse(s: &str) -> Option<Source> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let name = parts.next().unwrap_or("");
+
+        match kind {
+            "key" => Keycode::from_name(name).map(Source::Key),
+            "button" => Button::from_string(name).map(Source::Button),
+            "axis" => {
+                let (name, sign) = if name.ends_with('+') {
+                    (&name[..name.len() - 1], Sign::Positive)
+                } else if name.ends_with('-') {
+                    (&name[..name.len() - 1], Sign::Negative)
+                } else {
+                    return None;
+                };
+                Axis::from_string(name).map(|axis| Source::Axis(axis, sign))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            Source::Key(key) => format!("key:{}", key.name()),
+            Source::Button(button) => format!("button:{}", button.string()),
+            Source::Axis(axis, Sign::Positive) => format!("axis:{}+", axis.string()),
+            Source::Axis(axis, Sign::Negative) => format!("axis:{}-", axis.string()),
+        }
+    }
+
+    /// Whether both come from the same kind of device input, rebinding
+    /// replaces the old binding of the same kind.
+    fn same_kind(&self, other: &Source) -> bool {
+        match (*self, *other) {
+            (Source::Key(_), Source::Key(_)) |
+            (Source::Button(_), Source::Button(_)) |
+            (Source::Axis(..), Source::Axis(..)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Turns raw stick positions into something usable: nothing inside the
+/// deadzone, then rising from 0 to 1 along `value^exponent`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisCurve {
+    pub deadzone: f64,
+    pub exponent: f64,
+}
+
+impl Default for AxisCurve {
+    fn default() -> Self {
+        AxisCurve {
+            deadzone: 0.23,
+            exponent: 1.0,
+        }
+    }
+}
+
+impl AxisCurve {
+    /// Maps a stick position in `0..1` to the strength of the action.
+    pub fn apply(&self, value: f64) -> f64 {
+        if value <= self.deadzone {
+            0.0
+        } else {
+            ((value - self.deadzone) / (1.0 - self.deadzone))
+                .min(1.0)
+                .powf(self.exponent)
+        }
+    }
+}
+
+/// Tracks the actions of one player: how strongly each is held and whether
+/// it was pressed or released since the last frame.
+///
+/// Device events go in as they arrive, the game reads the actions once per
+/// frame and calls `end_frame`.
+pub struct Input {
+    bindings: Bindings,
+    active: Vec<(Source, f64)>,
+    values: [f64; 7],
+    pressed: [bool; 7],
+    released: [bool; 7],
+    rebinding: Vec<Action>,
+    changed: bool,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Input {
+        Input {
+            bindings,
+            active: vec![],
+            values: [0.0; 7],
+            pressed: [false; 7],
+            released: [false; 7],
+            rebinding: vec![],
+            changed: false,
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// How strongly the action is held, from 0 to 1.
+    pub fn value(&self, action: Action) -> f64 {
+        self.values[action.index()]
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.value(action) > 0.0
+    }
+
+    /// Pressed since the last frame, even if already let go again.
+    pub fn was_pressed(&self, action: Action) -> bool {
+        self.pressed[action.index()]
+    }
+
+    pub fn was_released(&self, action: Action) -> bool {
+        self.released[action.index()]
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed = [false; 7];
+        self.released = [false; 7];
+    }
+
+    pub fn key_down(&mut self, key: Keycode) {
+        self.source(Source::Key(key), 1.0);
+    }
+
+    pub fn key_up(&mut self, key: Keycode) {
+        self.source(Source::Key(key), 0.0);
+    }
+
+    pub fn button_down(&mut self, button: Button) {
+        self.source(Source::Button(button), 1.0);
+    }
+
+    pub fn button_up(&mut self, button: Button) {
+        self.source(Source::Button(button), 0.0);
+    }
+
+    pub fn axis(&mut self, axis: Axis, raw: i16) {
+        let value = (raw as f64 / i16::max_value() as f64).max(-1.0).min(1.0);
+        let curve = self.bindings.curve;
+
+        self.source(Source::Axis(axis, Sign::Positive), curve.apply(value));
+        self.source(Source::Axis(axis, Sign::Negative), curve.apply(-value));
+    }
+
+    /// Binds the next inputs to `actions`, one each, in order. The input
+    /// used for rebinding doesn't trigger anything.
+    pub fn rebind(&mut self, actions: &[Action]) {
+        self.rebinding = actions.to_vec();
+    }
+
+    /// The action waiting for an input to be bound to.
+    pub fn rebinding(&self) -> Option<Action> {
+        self.rebinding.first().cloned()
+    }
+
+    /// Whether a rebinding finished since the last call, so the bindings
+    /// can be saved.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+
+    fn source(&mut self, source: Source, strength: f64) {
+        if strength > 0.0 && !self.rebinding.is_empty() {
+            // Sticks wander, only bind them when pushed properly.
+            let deliberate = match source {
+                Source::Axis(..) => strength >= 0.5,
+                _ => true,
+            };
+            if deliberate && !self.active.iter().any(|&(s, _)| s == source) {
+                let action = self.rebinding.remove(0);
+                self.bindings.bind(action, source);
+                self.changed = self.rebinding.is_empty();
+                // Held until let go, so it can't bind the next action too.
+                self.active.push((source, strength));
+            }
+            return;
+        }
+
+        self.active.retain(|&(s, _)| s != source);
+        if strength > 0.0 {
+            self.active.push((source, strength));
+        }
+
+        for &action in ACTIONS.iter() {
+            let value = self.active
+                .iter()
+                .filter(|&&(s, _)| self.bindings.is_bound(action, s))
+                .fold(0.0, |v, &(_, strength)| f64::max(v, strength));
+
+            let ix = action.index();
+            let was_held = self.values[ix] > 0.0;
+            if !was_held && value > 0.0 {
+                self.pressed[ix] = true;
+            }
+            if was_held && value <= 0.0 {
+                self.released[ix] = true;
+            }
+            self.values[ix] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> Input {
+        let mut bindings = Bindings::new("test", AxisCurve::default());
+        bindings.bind(Action::Jump, Source::Key(Keycode::Space));
+        bindings.bind(Action::Jump, Source::Button(Button::A));
+        bindings.bind(Action::Right, Source::Axis(Axis::LeftX, Sign::Positive));
+        bindings.bind(Action::Left, Source::Axis(Axis::LeftX, Sign::Negative));
+        Input::new(bindings)
+    }
+
+    #[test]
+    fn edges_last_one_frame() {
+        let mut input = input();
+
+        input.key_down(Keycode::Space);
+        input.key_up(Keycode::Space);
+        assert!(input.was_pressed(Action::Jump));
+        assert!(input.was_released(Action::Jump));
+        assert!(!input.is_held(Action::Jump));
+
+        input.end_frame();
+        assert!(!input.was_pressed(Action::Jump));
+        assert!(!input.was_released(Action::Jump));
+    }
+
+    #[test]
+    fn held_while_any_source_is() {
+        let mut input = input();
+
+        input.key_down(Keycode::Space);
+        input.button_down(Button::A);
+        input.end_frame();
+        input.key_up(Keycode::Space);
+        assert!(input.is_held(Action::Jump));
+        assert!(!input.was_released(Action::Jump));
+
+        input.button_up(Button::A);
+        assert!(input.was_released(Action::Jump));
+    }
+
+    #[test]
+    fn axis_deadzone_and_direction() {
+        let mut input = input();
+
+        input.axis(Axis::LeftX, 5000);
+        assert!(!input.is_held(Action::Right));
+
+        input.axis(Axis::LeftX, i16::max_value());
+        assert_eq!(input.value(Action::Right), 1.0);
+        assert!(!input.is_held(Action::Left));
+
+        input.axis(Axis::LeftX, -i16::max_value());
+        assert!(input.was_released(Action::Right));
+        assert!(input.is_held(Action::Left));
+    }
+
+    #[test]
+    fn rebinding_replaces_the_same_kind() {
+        let mut input = input();
+
+        input.rebind(&[Action::Jump]);
+        input.key_down(Keycode::W);
+        assert!(!input.was_pressed(Action::Jump));
+        assert!(input.take_changed());
+        assert_eq!(input.rebinding(), None);
+
+        input.key_down(Keycode::Space);
+        assert!(!input.is_held(Action::Jump));
+        input.button_down(Button::A);
+        assert!(input.is_held(Action::Jump));
+    }
+}
@@ -0,0 +1,109 @@
+use ggez::{Context, GameError, GameResult};
+use serde_json;
+
+use std::io::Read;
+
+use physics::{Trigger, TriggerKind};
+use Vector2;
+
+/// Gameplay data of a level that lives next to its art in
+/// `resources/level/<name>/level.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LevelData {
+    pub triggers: Vec<TriggerData>,
+    /// Where players start, one each, wrapping around when there are more
+    /// players than spawns.
+    pub spawns: Vec<SpawnData>,
+    pub camera_zones: Vec<CameraZoneData>,
+    pub camera_paths: Vec<CameraPathData>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SpawnData {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl SpawnData {
+    pub fn position(&self) -> Vector2 {
+        Vector2::new(self.x, self.y)
+    }
+}
+
+/// A trigger rectangle in world units, `x` and `y` being its bottom left
+/// corner.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TriggerData {
+    pub name: String,
+    pub kind: TriggerKind,
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl TriggerData {
+    pub fn to_trigger(&self) -> Trigger {
+        let half_size = Vector2::new(self.w / 2.0, self.h / 2.0);
+        let center = Vector2::new(self.x, self.y) + half_size;
+        Trigger::new(&self.name, self.kind.clone(), center, half_size)
+    }
+}
+
+/// How the camera behaves while players are inside the `CameraZone`
+/// trigger of the same name.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraZoneData {
+    pub name: String,
+    /// Keeps the view centered at this x instead of following.
+    #[serde(default)]
+    pub lock_x: Option<f64>,
+    #[serde(default)]
+    pub lock_y: Option<f64>,
+    #[serde(default)]
+    pub zoom: Option<f64>,
+    /// Camera path played the first time anyone enters.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// A scripted camera pan through waypoints, in order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraPathData {
+    pub name: String,
+    pub waypoints: Vec<WaypointData>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WaypointData {
+    pub x: f64,
+    pub y: f64,
+    /// Keeps the zoom the camera had when left out.
+    #[serde(default)]
+    pub zoom: Option<f64>,
+    /// Seconds to get here from the previous waypoint.
+    pub duration: f64,
+    /// Seconds to stay before moving on.
+    #[serde(default)]
+    pub hold: f64,
+}
+
+impl WaypointData {
+    pub fn position(&self) -> Vector2 {
+        Vector2::new(self.x, self.y)
+    }
+}
+
+impl LevelData {
+    pub fn load(ctx: &mut Context, dir: &str) -> GameResult<LevelData> {
+        let file = ctx.filesystem.open(format!("{}/level.json", dir))?;
+        LevelData::from_reader(file, dir)
+    }
+
+    pub fn from_reader<R: Read>(reader: R, dir: &str) -> GameResult<LevelData> {
+        serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken level data in {}: {}", dir, e))
+        })
+    }
+}
@@ -1,9 +1,10 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use marker::*;
 use marker::geom::Rect;
 use super::MarkedTiles;
 
-use rand;
+use rand::{Rng, SeedableRng, StdRng};
 
 #[derive(Debug)]
 struct GroundIndex {
@@ -19,15 +20,17 @@ struct ObjectIndex {
     surface: Vec<Rect>,
 }
 
-#[derive(Debug)]
 pub struct LevelAssetIndex {
     ground: GroundIndex,
     platforms: PlatformIndex,
     objects: ObjectIndex,
+    /// Picks between tiles that fit equally well, seeded so the same seed
+    /// dresses the level the same way.
+    rng: RefCell<StdRng>,
 }
 
 impl LevelAssetIndex {
-    pub fn build(ground: &MarkedTiles, objects: &MarkedTiles) -> LevelAssetIndex {
+    pub fn build(ground: &MarkedTiles, objects: &MarkedTiles, seed: u64) -> LevelAssetIndex {
         let mut ground_sqr: HashMap<Square, Vec<Rect>> = HashMap::with_capacity(3);
         let mut platform_hor: HashMap<Horizontal, Vec<Rect>> = HashMap::with_capacity(3);
         let mut ground_obj = vec![];
@@ -75,16 +78,25 @@ impl LevelAssetIndex {
                 surface: surface_obj,
             },
             platforms: PlatformIndex { horizontal: platform_hor },
+            rng: RefCell::new(StdRng::from_seed(&[seed as usize][..])),
         };
 
         index
     }
 
     pub fn find_ground(&self, sqr: Square) -> Option<Rect> {
-        self.ground.square.get(&sqr).and_then(random_from)
+        let mut rng = self.rng.borrow_mut();
+        self.ground
+            .square
+            .get(&sqr)
+            .and_then(|from| random_from(&mut *rng, from))
     }
     pub fn find_platform(&self, hor: Horizontal) -> Option<Rect> {
-        self.platforms.horizontal.get(&hor).and_then(random_from)
+        let mut rng = self.rng.borrow_mut();
+        self.platforms
+            .horizontal
+            .get(&hor)
+            .and_then(|from| random_from(&mut *rng, from))
     }
 
     pub fn find_object(&self, surface: bool) -> Option<Rect> {
@@ -93,14 +105,14 @@ impl LevelAssetIndex {
         } else {
             &self.objects.ground
         };
-        random_from(&r)
+        random_from(&mut *self.rng.borrow_mut(), &r)
     }
 }
 
 
-fn random_from<T: Clone>(from: &Vec<T>) -> Option<T> {
+fn random_from<R: Rng, T: Clone>(rng: &mut R, from: &Vec<T>) -> Option<T> {
     if from.len() > 0 {
-        let ix = rand::random::<usize>() % from.len();
+        let ix = rng.gen::<usize>() % from.len();
         Some(from[ix].clone())
     } else {
         None
@@ -9,38 +9,107 @@ use marker::*;
 use marker::geom::*;
 
 pub mod index;
+mod query;
+mod data;
+mod parallax;
+
+pub use self::query::*;
+pub use self::data::*;
+pub use self::parallax::*;
 
 use ggez::graphics::DrawParam;
 use ggez::graphics;
 
 use na::Vector2;
 
+use physics::quad_tree;
+
 use self::index::LevelAssetIndex;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum LevelType {
     Graveyard,
 }
 
+impl LevelType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LevelType::Graveyard => "graveyard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LevelType> {
+        match name {
+            "graveyard" => Some(LevelType::Graveyard),
+            _ => None,
+        }
+    }
+
+    /// The level after this one, the last one leads back to the first.
+    pub fn next(&self) -> LevelType {
+        match *self {
+            LevelType::Graveyard => LevelType::Graveyard,
+        }
+    }
+
+    /// Where the level data lives in the resources.
+    pub fn dir(&self) -> String {
+        format!("/level/{}", self.name())
+    }
+
+    /// The tiles, rows top to bottom: 0 is empty, 1 a block, 2 a one-way
+    /// platform.
+    pub fn grid(&self) -> Vec<Vec<usize>> {
+        match *self {
+            LevelType::Graveyard => graveyard(),
+        }
+    }
+}
+
+fn graveyard() -> Vec<Vec<usize>> {
+    vec![
+        vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 2, 1, 1, 1, 1, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    ]
+}
+
 pub struct LevelAssets {
     pub ground: MarkedTiles,
     pub objects: MarkedTiles,
-    pub background: Image,
+    pub parallax: Parallax,
 }
 
 impl LevelAssets {
     pub fn load_assets<'a>(ctx: &mut Context, tpe: LevelType) -> GameResult<LevelAssets> {
-        let (g, o, bg) = match tpe {
+        let (g, o) = match tpe {
             LevelType::Graveyard => {
                 let g = Loader::load_sprite_sheet(ctx, "/level/graveyard/level_ground")?;
                 let o = Loader::load_sprite_sheet(ctx, "/level/graveyard/level_objects")?;
-                let bg = Image::new(ctx, "/level/graveyard/background.png")?;
-                (g, o, bg)
+                (g, o)
             }
         };
         Ok(LevelAssets {
             ground: g,
             objects: o,
-            background: bg,
+            parallax: Parallax::load(ctx, &tpe.dir())?,
         })
     }
 }
@@ -49,40 +118,23 @@ pub struct Level {
     pub terrain: Vec<Vec<usize>>,
     pub assets: LevelAssets,
     pub index: LevelAssetIndex,
+    pub data: LevelData,
 }
 
 impl Level {
-    pub fn load(ctx: &mut Context, lt: LevelType) -> GameResult<Level> {
+    /// Loads a level, `seed` picks the tile variants.
+    pub fn load(ctx: &mut Context, lt: LevelType, seed: u64) -> GameResult<Level> {
+        let data = LevelData::load(ctx, &lt.dir())?;
         let assets = LevelAssets::load_assets(ctx, lt)?;
-        let terrain = vec![
-            vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 2, 1, 1, 1, 1, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-            vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-        ];
-
-        let index = LevelAssetIndex::build(&assets.ground, &assets.objects);
+        let terrain = lt.grid();
+
+        let index = LevelAssetIndex::build(&assets.ground, &assets.objects, seed);
 
         Ok(Level {
             assets,
             terrain,
             index,
+            data,
         })
     }
 }
@@ -167,6 +219,68 @@ impl Terrain {
             false
         }
     }
+
+    /// The area covered by tiles.
+    pub fn bounds(&self) -> quad_tree::Rect {
+        let half_tile = self.tile_size / 2.0;
+        quad_tree::Rect::new(
+            self.position.x - half_tile,
+            self.position.y - half_tile,
+            self.width as f64 * self.tile_size,
+            self.height as f64 * self.tile_size,
+        )
+    }
+
+    /// Builds the collision side of a level grid, see `LevelType::grid`.
+    pub fn from_grid(grid: &Vec<Vec<usize>>) -> Terrain {
+        let mut terrain: Vec<Vec<TileType>> = grid.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|tile| match *tile {
+                        1 => TileType::Block,
+                        2 => TileType::OneWay,
+                        _ => TileType::Empty,
+                    })
+                    .collect()
+            })
+            .collect();
+        terrain.reverse();
+
+        Terrain {
+            width: terrain[0].len(),
+            height: terrain.len(),
+            terrain,
+            position: Vector2::new(0.0, 128.0),
+            tile_size: 128.0,
+        }
+    }
+
+    /// Builds a terrain from rows drawn top to bottom, `#` for blocks, `=`
+    /// for one-way platforms and anything else for empty space. Tile (0, 0)
+    /// is centered on the origin.
+    #[cfg(test)]
+    pub fn from_ascii(rows: &[&str], tile_size: f64) -> Terrain {
+        let mut terrain: Vec<Vec<TileType>> = rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        '#' => TileType::Block,
+                        '=' => TileType::OneWay,
+                        _ => TileType::Empty,
+                    })
+                    .collect()
+            })
+            .collect();
+        terrain.reverse();
+
+        Terrain {
+            width: terrain[0].len(),
+            height: terrain.len(),
+            terrain,
+            position: Vector2::new(0.0, 0.0),
+            tile_size,
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -185,29 +299,12 @@ pub struct RenderableLevel {
 impl RenderableLevel {
     pub fn build(level: Level) -> RenderableLevel {
         let mut sprites: Vec<(Rc<Image>, DrawParam)> = vec![];
-        let mut terrain_vec: Vec<Vec<TileType>> = vec![];
+        let terrain = Terrain::from_grid(&level.terrain);
 
         let height = level.terrain.len();
         let pixel_height = height * 128;
         let width = level.terrain[0].len();
 
-        for v_vec in level.terrain.iter() {
-            let mut h_vec = vec![];
-
-            for tile in v_vec.iter() {
-                match tile {
-                    &0 => h_vec.push(TileType::Empty),
-                    &1 => h_vec.push(TileType::Block),
-                    &2 => h_vec.push(TileType::OneWay),
-                    _ => h_vec.push(TileType::Empty),
-                }
-            }
-
-
-            h_vec.shrink_to_fit();
-            terrain_vec.push(h_vec);
-        }
-
         {
             let is_left_wall = |h| h == 0;
             let is_right_wall = |h| h == width - 1;
@@ -358,17 +455,10 @@ impl RenderableLevel {
             }
         };
 
-        terrain_vec.reverse();
         RenderableLevel {
             sprites: sprites,
             level,
-            terrain: Terrain {
-                terrain: terrain_vec,
-                position: Vector2::new(0.0, 128.0),
-                width: width,
-                height: height,
-                tile_size: 128.0,
-            },
+            terrain,
         }
     }
 }
@@ -0,0 +1,168 @@
+use ggez::{Context, GameError, GameResult};
+use ggez::graphics;
+use ggez::graphics::{Color, DrawParam, Image, Point};
+use serde_json;
+
+use std::io::Read;
+use std::ops::Range;
+
+use camera::*;
+use Vector2;
+
+/// The look of a level beyond its tiles, from
+/// `resources/level/<name>/theme.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeData {
+    /// Drawn in order, the first furthest back.
+    pub layers: Vec<LayerData>,
+}
+
+/// An image scrolling along with the camera at its own pace.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayerData {
+    /// Relative to the level directory.
+    pub image: String,
+    /// How much of the camera movement the layer follows: 0 stays put on
+    /// screen like the sky, 1 moves with the level, more passes in front.
+    pub scroll_x: f64,
+    pub scroll_y: f64,
+    /// Where the center of the image is with the camera at the origin, `y`
+    /// anchors the layer vertically.
+    pub x: f64,
+    pub y: f64,
+    pub scale: f64,
+    /// Repeats the image to fill the view along the axis.
+    pub repeat_x: bool,
+    pub repeat_y: bool,
+    /// Colour multiplied in, alpha included.
+    pub tint: [f32; 4],
+    /// Drawn over the players and the level instead of behind them.
+    pub foreground: bool,
+}
+
+impl Default for LayerData {
+    fn default() -> Self {
+        LayerData {
+            image: String::new(),
+            scroll_x: 1.0,
+            scroll_y: 1.0,
+            x: 0.0,
+            y: 0.0,
+            scale: 1.0,
+            repeat_x: false,
+            repeat_y: false,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            foreground: false,
+        }
+    }
+}
+
+impl ThemeData {
+    pub fn load(ctx: &mut Context, dir: &str) -> GameResult<ThemeData> {
+        let file = ctx.filesystem.open(format!("{}/theme.json", dir))?;
+        ThemeData::from_reader(file, dir)
+    }
+
+    pub fn from_reader<R: Read>(reader: R, dir: &str) -> GameResult<ThemeData> {
+        serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken theme in {}: {}", dir, e))
+        })
+    }
+}
+
+/// The layers of a theme along with their images.
+pub struct Parallax {
+    layers: Vec<(LayerData, Image)>,
+}
+
+impl Parallax {
+    pub fn load(ctx: &mut Context, dir: &str) -> GameResult<Parallax> {
+        let theme = ThemeData::load(ctx, dir)?;
+        let mut layers = vec![];
+
+        for layer in theme.layers.into_iter() {
+            let image = Image::new(ctx, format!("{}/{}", dir, layer.image))?;
+            layers.push((layer, image));
+        }
+
+        Ok(Parallax { layers })
+    }
+
+    pub fn draw_background(&self, ctx: &mut Context, camera: &Camera) -> GameResult<()> {
+        self.draw(ctx, camera, false)
+    }
+
+    pub fn draw_foreground(&self, ctx: &mut Context, camera: &Camera) -> GameResult<()> {
+        self.draw(ctx, camera, true)
+    }
+
+    fn draw(&self, ctx: &mut Context, camera: &Camera, foreground: bool) -> GameResult<()> {
+        for &(ref layer, ref image) in self.layers.iter() {
+            if layer.foreground == foreground {
+                draw_layer(ctx, camera, layer, image)?;
+            }
+        }
+        graphics::set_color(ctx, graphics::WHITE)
+    }
+}
+
+fn draw_layer(ctx: &mut Context, camera: &Camera, layer: &LayerData, image: &Image) -> GameResult<()> {
+    let size = Vector2::new(image.width() as f64, image.height() as f64) * layer.scale;
+    let at = camera.location();
+    let view = camera.size();
+    let origin = Vector2::new(
+        layer.x + at.x * (1.0 - layer.scroll_x),
+        layer.y + at.y * (1.0 - layer.scroll_y),
+    );
+
+    let xs = copies(origin.x, size.x, at.x, view.x, layer.repeat_x);
+    let ys = copies(origin.y, size.y, at.y, view.y, layer.repeat_y);
+
+    let tint = layer.tint;
+    graphics::set_color(ctx, Color::new(tint[0], tint[1], tint[2], tint[3]))?;
+
+    for i in xs {
+        for j in ys.clone() {
+            let dest = origin + Vector2::new(i as f64 * size.x, j as f64 * size.y);
+            image.draw_ex_camera(
+                camera,
+                ctx,
+                DrawParam {
+                    dest: Point::new(dest.x as f32, dest.y as f32),
+                    scale: Point::new(layer.scale as f32, layer.scale as f32),
+                    ..Default::default()
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Which copies of a layer centered at `origin + k * size` are needed to
+/// cover a view of `view` centered at `at`, one spare each side for a turned
+/// camera.
+fn copies(origin: f64, size: f64, at: f64, view: f64, repeat: bool) -> Range<i64> {
+    if !repeat || size <= 0.0 {
+        return 0..1;
+    }
+
+    let first = ((at - view / 2.0 - origin) / size - 0.5).ceil() as i64;
+    let last = ((at + view / 2.0 - origin) / size + 0.5).floor() as i64;
+    first - 1..last + 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_cover_the_view() {
+        assert_eq!(copies(0.0, 100.0, 0.0, 300.0, false), 0..1);
+        // Copies 0 and 1 reach from -50 to 150, more spill over the edges.
+        assert_eq!(copies(0.0, 100.0, 50.0, 200.0, true), -2..4);
+        assert_eq!(copies(1000.0, 100.0, 0.0, 100.0, true), -12..-7);
+    }
+}
@@ -0,0 +1,493 @@
+//! Spatial queries against the tile grid.
+//!
+//! One-way platforms only count as ground when approached from above: rays
+//! and boxes hit them through their top face while moving down and pass
+//! through them in every other direction.
+
+use super::{Terrain, TileType};
+use Vector2;
+
+use std::f64;
+
+/// Slack allowed on contacts, so a box resting exactly on a face isn't
+/// tripped up by rounding when it moves along or away from it.
+const CONTACT_EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RayHit {
+    pub tile: (isize, isize),
+    pub point: Vector2,
+    pub normal: Vector2,
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxHit {
+    pub tile: (isize, isize),
+    /// Center of the box at the moment of contact.
+    pub center: Vector2,
+    pub normal: Vector2,
+    pub distance: f64,
+    /// Fraction of the cast delta travelled before the contact, in `[0, 1]`.
+    pub time: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileFilter {
+    /// Only `TileType::Block`.
+    Solid,
+    /// Blocks and one-way platforms.
+    Ground,
+}
+
+impl TileFilter {
+    fn accepts(&self, tile: TileType) -> bool {
+        match (*self, tile) {
+            (_, TileType::Block) => true,
+            (TileFilter::Ground, TileType::OneWay) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Terrain {
+    /// Index of the tile containing `x`, rounding down for points left of the
+    /// grid, unlike `get_tile_x_at_point`.
+    pub fn tile_floor_x(&self, x: f64) -> isize {
+        ((x - self.position.x + self.tile_size / 2.0) / self.tile_size).floor() as isize
+    }
+
+    pub fn tile_floor_y(&self, y: f64) -> isize {
+        ((y - self.position.y + self.tile_size / 2.0) / self.tile_size).floor() as isize
+    }
+
+    /// Bottom left and top right corners of a tile.
+    pub fn tile_bounds(&self, x: isize, y: isize) -> (Vector2, Vector2) {
+        let center = self.get_map_tile_position(x, y);
+        let half = Vector2::new(self.tile_size / 2.0, self.tile_size / 2.0);
+        (center - half, center + half)
+    }
+
+    /// Walks the grid along the ray and returns the first tile it enters that
+    /// blocks it. The tile the ray starts in is never reported.
+    pub fn raycast(&self, origin: Vector2, direction: Vector2, max_distance: f64) -> Option<RayHit> {
+        let len = direction.norm();
+        if len == 0.0 || max_distance <= 0.0 {
+            return None;
+        }
+        let dir = direction / len;
+
+        let mut tx = self.tile_floor_x(origin.x);
+        let mut ty = self.tile_floor_y(origin.y);
+        let (min, max) = self.tile_bounds(tx, ty);
+
+        let (step_x, mut t_max_x, t_delta_x) = dda_axis(origin.x, dir.x, min.x, max.x, self.tile_size);
+        let (step_y, mut t_max_y, t_delta_y) = dda_axis(origin.y, dir.y, min.y, max.y, self.tile_size);
+
+        loop {
+            let (t, normal) = if t_max_x < t_max_y {
+                let t = t_max_x;
+                tx += step_x;
+                t_max_x += t_delta_x;
+                (t, Vector2::new(-step_x as f64, 0.0))
+            } else {
+                let t = t_max_y;
+                ty += step_y;
+                t_max_y += t_delta_y;
+                (t, Vector2::new(0.0, -step_y as f64))
+            };
+
+            if t > max_distance {
+                return None;
+            }
+
+            let blocks = match self.get_tile(tx, ty) {
+                TileType::Block => true,
+                TileType::OneWay => normal.y > 0.0,
+                TileType::Empty => false,
+            };
+
+            if blocks {
+                return Some(RayHit {
+                    tile: (tx, ty),
+                    point: origin + dir * t,
+                    normal,
+                    distance: t,
+                });
+            }
+        }
+    }
+
+    /// Whether nothing solid lies on the segment between two points.
+    pub fn line_of_sight(&self, from: Vector2, to: Vector2) -> bool {
+        let d = to - from;
+        self.raycast(from, d, d.norm()).is_none()
+    }
+
+    /// Sweeps a box by `delta` and returns the earliest contact.
+    ///
+    /// Tiles the box already overlaps are ignored, so a stuck body can always
+    /// move out. One-way platforms are hit only while moving down with the
+    /// box bottom no further than `platform_threshold` below their top.
+    /// Faces shared by two solid tiles never report a hit, which keeps boxes
+    /// from snagging on seams of flat floors and walls.
+    pub fn box_cast(
+        &self,
+        center: Vector2,
+        half_size: Vector2,
+        delta: Vector2,
+        platform_threshold: f64,
+    ) -> Option<BoxHit> {
+        let end = center + delta;
+        let x0 = self.tile_floor_x(center.x.min(end.x) - half_size.x);
+        let x1 = self.tile_floor_x(center.x.max(end.x) + half_size.x);
+        let y0 = self.tile_floor_y(center.y.min(end.y) - half_size.y);
+        let y1 = self.tile_floor_y(center.y.max(end.y) + half_size.y);
+
+        let mut best: Option<BoxHit> = None;
+
+        for ty in y0..y1 + 1 {
+            for tx in x0..x1 + 1 {
+                let hit = match self.get_tile(tx, ty) {
+                    TileType::Block => self.box_vs_block(tx, ty, center, half_size, delta),
+                    TileType::OneWay => {
+                        self.box_vs_platform(tx, ty, center, half_size, delta, platform_threshold)
+                    }
+                    TileType::Empty => None,
+                };
+
+                if let Some(hit) = hit {
+                    let better = match best {
+                        None => true,
+                        Some(ref b) => {
+                            hit.time < b.time ||
+                                (hit.time == b.time && hit.normal.y != 0.0 && b.normal.y == 0.0)
+                        }
+                    };
+                    if better {
+                        best = Some(hit);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn box_vs_block(
+        &self,
+        tx: isize,
+        ty: isize,
+        center: Vector2,
+        half_size: Vector2,
+        delta: Vector2,
+    ) -> Option<BoxHit> {
+        let (min, max) = self.tile_bounds(tx, ty);
+        let (min, max) = (min - half_size, max + half_size);
+
+        let (enter_x, exit_x, enter_y, exit_y) = match (
+            slab(center.x, delta.x, min.x, max.x),
+            slab(center.y, delta.y, min.y, max.y),
+        ) {
+            (Some((enter_x, exit_x)), Some((enter_y, exit_y))) => (enter_x, exit_x, enter_y, exit_y),
+            _ => return None,
+        };
+
+        let enter = enter_x.max(enter_y);
+        let exit = exit_x.min(exit_y);
+
+        if enter >= exit || enter > 1.0 || exit <= 0.0 ||
+            enter * delta.norm() < -CONTACT_EPSILON
+        {
+            return None;
+        }
+
+        let x_normal = Vector2::new(-delta.x.signum(), 0.0);
+        let y_normal = Vector2::new(0.0, -delta.y.signum());
+        let x_open = enter_x == enter && delta.x != 0.0 && !self.is_internal_face(tx, ty, &x_normal);
+        let y_open = enter_y == enter && delta.y != 0.0 && !self.is_internal_face(tx, ty, &y_normal);
+
+        let normal = if y_open {
+            y_normal
+        } else if x_open {
+            x_normal
+        } else {
+            return None;
+        };
+
+        let enter = enter.max(0.0);
+        let mut contact = center + delta * enter;
+        if normal.x != 0.0 {
+            contact.x = if normal.x > 0.0 { max.x } else { min.x };
+        } else {
+            contact.y = if normal.y > 0.0 { max.y } else { min.y };
+        }
+
+        Some(BoxHit {
+            tile: (tx, ty),
+            center: contact,
+            normal,
+            distance: delta.norm() * enter,
+            time: enter,
+        })
+    }
+
+    fn box_vs_platform(
+        &self,
+        tx: isize,
+        ty: isize,
+        center: Vector2,
+        half_size: Vector2,
+        delta: Vector2,
+        platform_threshold: f64,
+    ) -> Option<BoxHit> {
+        if delta.y >= 0.0 {
+            return None;
+        }
+
+        let (min, max) = self.tile_bounds(tx, ty);
+        let bottom = center.y - half_size.y;
+
+        if bottom < max.y - platform_threshold || bottom + delta.y >= max.y {
+            return None;
+        }
+
+        let time = ((max.y - bottom) / delta.y).max(0.0);
+        let x = center.x + delta.x * time;
+        if x - half_size.x >= max.x || x + half_size.x <= min.x {
+            return None;
+        }
+
+        Some(BoxHit {
+            tile: (tx, ty),
+            center: Vector2::new(x, max.y + half_size.y),
+            normal: Vector2::new(0.0, 1.0),
+            distance: delta.norm() * time,
+            time,
+        })
+    }
+
+    /// A face is internal when the tile it faces is solid as well.
+    fn is_internal_face(&self, tx: isize, ty: isize, normal: &Vector2) -> bool {
+        self.is_obstacle(tx + normal.x as isize, ty + normal.y as isize)
+    }
+
+    /// First tile under the point accepted by the filter.
+    pub fn overlap_point(&self, point: Vector2, filter: TileFilter) -> Option<(isize, isize)> {
+        let tile = (self.tile_floor_x(point.x), self.tile_floor_y(point.y));
+        if filter.accepts(self.get_tile(tile.0, tile.1)) {
+            Some(tile)
+        } else {
+            None
+        }
+    }
+
+    /// All tiles accepted by the filter that the rectangle overlaps. Touching
+    /// a tile edge does not count as overlapping it.
+    pub fn overlap_rect(
+        &self,
+        center: Vector2,
+        half_size: Vector2,
+        filter: TileFilter,
+    ) -> Vec<(isize, isize)> {
+        let min = center - half_size;
+        let max = center + half_size;
+        let mut tiles = vec![];
+
+        for ty in self.tile_floor_y(min.y)..self.tile_floor_y(max.y) + 1 {
+            for tx in self.tile_floor_x(min.x)..self.tile_floor_x(max.x) + 1 {
+                let (tmin, tmax) = self.tile_bounds(tx, ty);
+                let overlaps = min.x < tmax.x && max.x > tmin.x && min.y < tmax.y && max.y > tmin.y;
+                if overlaps && filter.accepts(self.get_tile(tx, ty)) {
+                    tiles.push((tx, ty));
+                }
+            }
+        }
+
+        tiles
+    }
+
+    pub fn is_rect_free(&self, center: Vector2, half_size: Vector2, filter: TileFilter) -> bool {
+        self.overlap_rect(center, half_size, filter).is_empty()
+    }
+}
+
+/// Step direction, distance to the first grid line and distance between grid
+/// lines along one axis of a normalized ray.
+fn dda_axis(origin: f64, dir: f64, min: f64, max: f64, tile_size: f64) -> (isize, f64, f64) {
+    if dir > 0.0 {
+        (1, (max - origin) / dir, tile_size / dir)
+    } else if dir < 0.0 {
+        (-1, (min - origin) / dir, tile_size / -dir)
+    } else {
+        (0, f64::INFINITY, f64::INFINITY)
+    }
+}
+
+/// Entry and exit times of a moving point through `[min, max]`. A point that
+/// doesn't move on this axis has to be strictly inside the slab.
+fn slab(p: f64, d: f64, min: f64, max: f64) -> Option<(f64, f64)> {
+    if d == 0.0 {
+        if p > min + CONTACT_EPSILON && p < max - CONTACT_EPSILON {
+            Some((f64::NEG_INFINITY, f64::INFINITY))
+        } else {
+            None
+        }
+    } else {
+        let t1 = (min - p) / d;
+        let t2 = (max - p) / d;
+        Some((t1.min(t2), t1.max(t2)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiles are 10 units wide, tile (0, 0) is centered on the origin and
+    // the last row is y = 0.
+    fn grid() -> Terrain {
+        Terrain::from_ascii(
+            &[
+                "........",
+                "...==...",
+                "........",
+                "#......#",
+                "########",
+            ],
+            10.0,
+        )
+    }
+
+    #[test]
+    fn ray_hits_floor_from_above() {
+        let hit = grid()
+            .raycast(Vector2::new(22.0, 30.0), Vector2::new(0.0, -1.0), 100.0)
+            .unwrap();
+        assert_eq!(hit.tile, (2, 0));
+        assert_eq!(hit.point, Vector2::new(22.0, 5.0));
+        assert_eq!(hit.normal, Vector2::new(0.0, 1.0));
+        assert_eq!(hit.distance, 25.0);
+    }
+
+    #[test]
+    fn ray_respects_max_distance() {
+        assert!(
+            grid()
+                .raycast(Vector2::new(22.0, 30.0), Vector2::new(0.0, -1.0), 20.0)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn ray_hits_wall_sideways() {
+        let hit = grid()
+            .raycast(Vector2::new(30.0, 10.0), Vector2::new(1.0, 0.0), 100.0)
+            .unwrap();
+        assert_eq!(hit.tile, (7, 1));
+        assert_eq!(hit.normal, Vector2::new(-1.0, 0.0));
+        assert_eq!(hit.distance, 35.0);
+    }
+
+    #[test]
+    fn one_way_blocks_rays_only_from_above() {
+        let t = grid();
+        let down = t.raycast(Vector2::new(30.0, 40.0), Vector2::new(0.0, -1.0), 100.0)
+            .unwrap();
+        assert_eq!(down.tile, (3, 3));
+        assert_eq!(down.point, Vector2::new(30.0, 35.0));
+
+        let up = t.raycast(Vector2::new(30.0, 10.0), Vector2::new(0.0, 1.0), 30.0);
+        assert!(up.is_none());
+    }
+
+    #[test]
+    fn ground_ahead_and_line_of_sight() {
+        let t = grid();
+        let ahead = Vector2::new(10.0 + 3.0 * 10.0, 12.0);
+        assert!(t.raycast(ahead, Vector2::new(0.0, -1.0), 10.0).is_some());
+        assert!(t.line_of_sight(Vector2::new(10.0, 10.0), Vector2::new(60.0, 10.0)));
+        assert!(!t.line_of_sight(Vector2::new(10.0, 10.0), Vector2::new(80.0, 10.0)));
+    }
+
+    #[test]
+    fn box_cast_lands_on_floor() {
+        let hit = grid()
+            .box_cast(
+                Vector2::new(30.0, 30.0),
+                Vector2::new(3.0, 4.0),
+                Vector2::new(0.0, -40.0),
+                0.0,
+            )
+            .unwrap();
+        assert_eq!(hit.normal, Vector2::new(0.0, 1.0));
+        assert_eq!(hit.center, Vector2::new(30.0, 9.0));
+        assert_eq!(hit.distance, 21.0);
+    }
+
+    #[test]
+    fn box_cast_slides_along_floor() {
+        let hit = grid().box_cast(
+            Vector2::new(20.0, 9.0),
+            Vector2::new(3.0, 4.0),
+            Vector2::new(30.0, 0.0),
+            0.0,
+        );
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn box_cast_hits_wall() {
+        let hit = grid()
+            .box_cast(
+                Vector2::new(50.0, 10.0),
+                Vector2::new(3.0, 4.0),
+                Vector2::new(30.0, 0.0),
+                0.0,
+            )
+            .unwrap();
+        assert_eq!(hit.tile, (7, 1));
+        assert_eq!(hit.normal, Vector2::new(-1.0, 0.0));
+        assert_eq!(hit.center.x, 62.0);
+    }
+
+    #[test]
+    fn box_cast_one_way_semantics() {
+        let t = grid();
+        let half = Vector2::new(3.0, 4.0);
+
+        let from_above = t.box_cast(Vector2::new(35.0, 45.0), half, Vector2::new(0.0, -20.0), 0.0)
+            .unwrap();
+        assert_eq!(from_above.center, Vector2::new(35.0, 39.0));
+
+        let from_below = t.box_cast(Vector2::new(35.0, 20.0), half, Vector2::new(0.0, 15.0), 0.0);
+        assert!(from_below.is_none());
+
+        let sideways = t.box_cast(Vector2::new(10.0, 30.0), half, Vector2::new(40.0, 0.0), 0.0);
+        assert!(sideways.is_none());
+
+        let sunk = t.box_cast(Vector2::new(35.0, 38.0), half, Vector2::new(0.0, -5.0), 2.0)
+            .unwrap();
+        assert_eq!(sunk.center, Vector2::new(35.0, 39.0));
+        assert_eq!(sunk.time, 0.0);
+    }
+
+    #[test]
+    fn overlap_queries_filter_one_way() {
+        let t = grid();
+        assert_eq!(t.overlap_point(Vector2::new(30.0, 30.0), TileFilter::Solid), None);
+        assert_eq!(
+            t.overlap_point(Vector2::new(30.0, 30.0), TileFilter::Ground),
+            Some((3, 3))
+        );
+        assert_eq!(
+            t.overlap_rect(Vector2::new(5.0, 5.0), Vector2::new(2.0, 2.0), TileFilter::Solid),
+            vec![(0, 0), (1, 0), (0, 1)]
+        );
+        assert!(t.is_rect_free(
+            Vector2::new(30.0, 10.0),
+            Vector2::new(4.0, 5.0),
+            TileFilter::Ground,
+        ));
+    }
+}
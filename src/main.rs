@@ -2,6 +2,9 @@ extern crate ggez;
 extern crate marker;
 extern crate nalgebra as na;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 
 pub type Point2 = na::Point2<f64>;
@@ -10,10 +13,17 @@ pub type Vector2 = na::Vector2<f64>;
 mod sprite;
 mod state;
 mod player;
+mod player_view;
 mod level;
 mod camera;
 mod physics;
 mod debug;
+mod input;
+mod party;
+mod simulation;
+mod view;
+mod hud;
+mod console;
 
 use ggez::conf;
 use ggez::event;
@@ -26,28 +36,59 @@ use ggez::event::{Keycode, Mod};
 use std::rc::Rc;
 use std::time::Duration;
 
-use debug::Debug;
-use state::StateMachine;
-use player::*;
+use console::{Command, Console};
+use debug::{AxisSides, Debug};
+use hud::{FrameTimes, Hud};
+use input::{Action, ACTIONS};
+use party::{Party, PartyConfig};
 use camera::*;
+use physics::{seconds, Contact, TriggerKind};
+use physics::world::World;
+use player_view::PlayerSprites;
+use simulation::{Impact, Simulation, TICK_RATE};
+use view::View;
 
 pub struct Game {
-    pub player: Player,
-    pub player_sm: StateMachine,
+    pub sim: Simulation,
     pub level: Rc<RenderableLevel>,
-    pub camera: Camera,
-    pub fixed_update: Duration,
+    pub level_type: LevelType,
+    pub player_sprites: PlayerSprites,
+    pub views: Vec<View>,
+    pub follow_config: FollowConfig,
+    /// The view of the whole window with a single player.
+    pub view_size: Vector2,
+    /// A view per player instead of one for all.
+    pub split_screen: bool,
+    /// An overview of the whole level in a corner.
+    pub overview: bool,
+    /// Start or stop recording on the next update, key events get no
+    /// context.
+    pub toggle_recording: bool,
+    /// The slot the next F2 rebinds.
+    pub rebind_slot: usize,
+    /// Rebuild the views on the next update.
+    pub relayout: bool,
+    /// For the timing debug channel.
+    pub frame_times: FrameTimes,
+    pub hud: Hud,
+    pub console: Console,
+    /// Where the sticks were, for logging them.
+    pub axis_sides: AxisSides,
 }
 
 impl Game {
+    /// Ticks the game holds still when someone gets hit.
+    const HIT_PAUSE_TICKS: u32 = 5;
+    /// Landings falling faster than this shake the view.
+    const HARD_LANDING: f64 = 2500.0;
+
     pub fn new(ctx: &mut Context) -> GameResult<Game> {
-        let level = Rc::new({
-            let l = Level::load(ctx, LevelType::Graveyard).unwrap();
-            let rl = RenderableLevel::build(l);
-            rl
-        });
+        let mut console = Console::new();
+        Debug::load(ctx, &mut console);
 
-        let (p, sm) = Player::new(ctx)?;
+        let player_sprites = PlayerSprites::load(ctx)?;
+        let level_type = LevelType::Graveyard;
+        let (level, sim) = Game::start(ctx, level_type, rand::random(), &player_sprites)?;
 
         let (w, h) = (ctx.conf.window_width, ctx.conf.window_height);
 
@@ -55,133 +96,439 @@ impl Game {
 
         let fov = w as f64 * 1.5;
 
-        Ok(Game {
-            player: p,
-            player_sm: sm,
+        let mut game = Game {
+            sim,
             level,
-            camera: Camera::new(w, h, fov, hc * fov),
-            fixed_update: Duration::from_secs(0),
-        })
+            level_type,
+            player_sprites,
+            views: vec![],
+            follow_config: FollowConfig::load(ctx)?,
+            view_size: Vector2::new(fov, hc * fov),
+            split_screen: false,
+            overview: false,
+            toggle_recording: false,
+            rebind_slot: 0,
+            relayout: false,
+            frame_times: FrameTimes::new(120),
+            hud: Hud::new()?,
+            console,
+            axis_sides: AxisSides::new(),
+        };
+        game.layout(ctx)?;
+        Ok(game)
     }
-}
 
+    /// Loads a level and puts the players in it, `seed` dresses the level.
+    fn start(
+        ctx: &mut Context,
+        level_type: LevelType,
+        seed: u64,
+        player_sprites: &PlayerSprites,
+    ) -> GameResult<(Rc<RenderableLevel>, Simulation)> {
+        let level = Rc::new(RenderableLevel::build(Level::load(ctx, level_type, seed)?));
+
+        let mut world = World::new(&level.terrain);
+        for t in level.level.data.triggers.iter() {
+            world.add_trigger(t.to_trigger());
+        }
 
-impl event::EventHandler for Game {
-    fn update(&mut self, ctx: &mut Context, dt: Duration) -> GameResult<()> {
-        // let update_start = timer::get_time_since_start(ctx);
+        let spawns: Vec<Vector2> = level.level.data.spawns.iter().map(|s| s.position()).collect();
+        let config = PartyConfig::load(ctx)?;
+        let party = Party::new(ctx, &config, &player_sprites.frames(), &spawns, &mut world)?;
 
-        let mut w = self::physics::world::World::new();
-        w.update_areas(&self.camera);
+        Ok((level, Simulation::new(party, world)))
+    }
 
-        self.player_sm.handle_events(&mut self.player);
+    /// Carries out a console command, returning what to tell the user.
+    fn run(&mut self, ctx: &mut Context, command: Command) -> Result<String, String> {
+        match command {
+            Command::Teleport { x, y, player } => {
+                let at = Vector2::new(x, y);
+                match self.sim.party.slots.get_mut(player) {
+                    Some(slot) => slot.teleport(at),
+                    None => return Err(format!("No player {}", player + 1)),
+                }
+                self.drop_recording();
+                for view in self.views.iter_mut().filter(|v| v.follows(player)) {
+                    view.director.follow.reset();
+                }
+                Ok(format!("Player {} at ({}, {})", player + 1, x, y))
+            }
+            Command::Set { name, value } => {
+                for slot in self.sim.party.slots.iter_mut() {
+                    match console::profile_value(&mut slot.player.profile, &name) {
+                        Some(v) => *v = value,
+                        None => return Err(format!("Can't set {}", name)),
+                    }
+                }
+                self.drop_recording();
+                Ok(format!("{} = {}", name, value))
+            }
+            Command::LoadLevel(name) => {
+                let level_type = match LevelType::from_name(&name) {
+                    Some(lt) => lt,
+                    None => return Err(format!("No level {}", name)),
+                };
+                self.load_level(ctx, level_type).map_err(|e| e.to_string())?;
+                Ok(format!("Loaded {}", name))
+            }
+            Command::ToggleDebug(channel) => {
+                let on = Debug::toggle(channel);
+                Ok(format!("{} {}", channel.name(), if on { "on" } else { "off" }))
+            }
+            Command::Help => Ok(console::help()),
+        }
+    }
 
-        self.player_sm
-            .update(&mut self.player, &dt, &self.level.terrain);
-        if timer::check_update_time(ctx, 30) {
-            self.player_sm.fixed_update(&mut self.player);
-        };
-        self.camera.move_to(self.player.mv.position);
+    /// Swaps in a new level with the players at its spawns.
+    fn load_level(&mut self, ctx: &mut Context, level_type: LevelType) -> GameResult<()> {
+        let (level, sim) = Game::start(ctx, level_type, rand::random(), &self.player_sprites)?;
+        self.level = level;
+        self.level_type = level_type;
+        self.sim = sim;
+        self.layout(ctx)
+    }
 
-        // let update_end = timer::get_time_since_start(ctx);
-        // let delta = update_end - update_start;
-        // println!("Fps: {}", timer::get_fps(ctx));
+    /// Sets up the views: one for everyone or one per player, plus the
+    /// overview.
+    fn layout(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let (w, h) = (ctx.conf.window_width, ctx.conf.window_height);
+        let players = self.sim.party.slots.len();
+
+        let mut views = vec![];
+        if self.split_screen {
+            for (ix, viewport) in view::split(w, h, players).into_iter().enumerate() {
+                let v = self.new_view(ctx, viewport, vec![ix])?;
+                views.push(v);
+            }
+        } else {
+            let window = graphics::Rect::new(0.0, 0.0, w as f32, h as f32);
+            let v = self.new_view(ctx, window, (0..players).collect())?;
+            views.push(v);
+        }
+        if self.overview {
+            let v = self.new_view(ctx, view::inset(w, h), vec![])?;
+            views.push(v);
+        }
 
+        self.views = views;
         Ok(())
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx);
+    /// Rebuilds the views on the same level. The new directors go on where
+    /// the old ones were: paths stay played and the camera zones players
+    /// are in count them in again.
+    fn rebuild_views(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let mut played: Vec<String> = vec![];
+        for view in self.views.iter() {
+            for name in view.director.played() {
+                if !played.contains(name) {
+                    played.push(name.clone());
+                }
+            }
+        }
+        self.layout(ctx)?;
+
+        for view in self.views.iter_mut() {
+            view.director.set_played(played.clone());
+            for &ix in view.players.iter() {
+                let body = match self.sim.party.slots.get(ix) {
+                    Some(slot) => slot.body,
+                    None => continue,
+                };
+                for id in self.sim.world.overlapping(body) {
+                    if let Some(trigger) = self.sim.world.trigger(id) {
+                        if let TriggerKind::CameraZone = trigger.kind {
+                            view.director.enter(&trigger.name, &view.camera);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let camera = &self.camera;
+    /// A view keeping the pixels per world unit of the full window.
+    fn new_view(
+        &self,
+        ctx: &mut Context,
+        viewport: graphics::Rect,
+        players: Vec<usize>,
+    ) -> GameResult<View> {
+        let (w, h) = (ctx.conf.window_width as f64, ctx.conf.window_height as f64);
+        let min_size = Vector2::new(
+            self.view_size.x * viewport.w as f64 / w,
+            self.view_size.y * viewport.h as f64 / h,
+        );
+        let director = Director::new(
+            Follow::new(self.follow_config.clone()),
+            self.level.level.data.camera_zones.clone(),
+            self.level.level.data.camera_paths.clone(),
+        );
+
+        View::new(ctx, viewport, min_size, director, players)
+    }
 
-        let bd_dp = graphics::DrawParam {
-            src: graphics::Rect::new(0.0, 0.0, 1.0, 1.0),
-            scale: graphics::Point::new(2.0, 2.0),
-            dest: graphics::Point::new(camera.location().x as f32 * 0.9, camera.location().y as f32 * 0.9),
-            ..Default::default()
-        };
+    /// Makes hits and hard landings felt, hands camera zones to the
+    /// director, logs triggers and moves on to the next level at an exit.
+    fn react(&mut self, ctx: &mut Context, impact: Impact) -> GameResult<()> {
+        match impact {
+            Impact::Hurt { slot, from } => {
+                let away = self.sim.party.slots[slot].player.mv.position - from;
+                for view in self.views.iter_mut() {
+                    // The whole game holds still, only views on the hurt
+                    // player shake.
+                    view.camera.effects.hit_pause(Game::HIT_PAUSE_TICKS);
+                    if !view.follows(slot) {
+                        continue;
+                    }
+                    let effects = &mut view.camera.effects;
+                    effects.add_trauma(0.5);
+                    if away.norm() > 0.0 {
+                        effects.kick(away.normalize() * 16.0);
+                    }
+                }
+            }
+            Impact::Landed { slot, speed } => if speed > Game::HARD_LANDING {
+                let hardness = ((speed - Game::HARD_LANDING) / Game::HARD_LANDING).min(1.0);
+                for view in self.views.iter_mut().filter(|v| v.follows(slot)) {
+                    let effects = &mut view.camera.effects;
+                    effects.add_trauma(0.3 * hardness);
+                    effects.kick(Vector2::new(0.0, -12.0 * hardness));
+                }
+            },
+            Impact::CameraZone {
+                slot,
+                name,
+                contact,
+            } => for view in self.views.iter_mut().filter(|v| v.follows(slot)) {
+                match contact {
+                    Contact::Enter => view.director.enter(&name, &view.camera),
+                    _ => view.director.leave(&name),
+                }
+            },
+            Impact::Triggered {
+                slot,
+                name,
+                kind,
+                contact,
+            } => Debug::trigger_event(&mut self.console, slot, &name, &kind, contact),
+            Impact::LevelExit { slot, name } => {
+                let next = self.level_type.next();
+                self.console.print(&format!(
+                    "Player {} took {}, on to {}",
+                    slot + 1,
+                    name,
+                    next.name()
+                ));
+                self.load_level(ctx, next)?;
+            }
+        }
+        Ok(())
+    }
 
-        self.level
-            .level
-            .assets
-            .background
-            .draw_ex_camera(camera, ctx, bd_dp)?;
+    /// Everything in the level as `camera` sees it.
+    fn draw_scene(&self, ctx: &mut Context, camera: &Camera) -> GameResult<()> {
+        let parallax = &self.level.level.assets.parallax;
+        parallax.draw_background(ctx, camera)?;
 
-        self.player_sm.draw(ctx, camera, &self.player);
+        for slot in self.sim.party.slots.iter() {
+            self.player_sprites
+                .draw(ctx, camera, &slot.player, &slot.sm.visible())?;
+        }
 
         for &(ref img, ref dp) in self.level.sprites.iter() {
             (&**img).draw_ex_camera(camera, ctx, dp.clone())?;
         }
+        parallax.draw_foreground(ctx, camera)?;
 
         Debug::draw_level_obstacles(ctx, &self.level.terrain, camera);
-        graphics::present(ctx);
+        Debug::draw_triggers(ctx, &self.sim.world, camera);
+        Debug::draw_quad_tree(ctx, &self.sim.world, camera);
+        for slot in self.sim.party.slots.iter() {
+            Debug::draw_player(ctx, &slot.player, &slot.sm, &self.level.terrain, camera);
+        }
 
         Ok(())
     }
 
-    fn key_down_event(&mut self, keycode: Keycode, _keymod: Mod, repeat: bool) {
-        if !repeat {
-            match keycode {
-                Keycode::Left => self.player.input.left = true,
-                Keycode::Right => self.player.input.right = true,
-                Keycode::Up => self.player.input.up = true,
-                Keycode::Down => self.player.input.down = true,
-                Keycode::LCtrl => self.player.input.slide = true,
-                Keycode::Space => self.player.input.jump = true,
-                Keycode::LShift => self.player.input.attack = true,
-                _ => (),
+    /// Saves the recording, or starts one. Recordings play back from the
+    /// spawns, so recording starts the level over.
+    fn record_or_save(&mut self, ctx: &mut Context) -> GameResult<()> {
+        if let Some(recording) = self.sim.stop_recording() {
+            match recording.save(ctx, "recording") {
+                Ok(()) => self.console
+                    .print(&format!("Saved {} ticks", recording.ticks.len())),
+                Err(e) => self.console.print(&e.to_string()),
             }
+            return Ok(());
         }
+
+        let seed = rand::random::<u64>();
+        let (level, mut sim) = Game::start(ctx, self.level_type, seed, &self.player_sprites)?;
+        sim.record(self.level_type.name(), seed);
+        self.level = level;
+        self.sim = sim;
+        self.layout(ctx)?;
+        self.console.print("Recording, F3 saves");
+        Ok(())
     }
 
-    fn key_up_event(&mut self, keycode: Keycode, _keymod: Mod, repeat: bool) {
-        if !repeat {
-            //wat?
-            match keycode {
-                Keycode::Left => self.player.input.left = false,
-                Keycode::Right => self.player.input.right = false,
-                Keycode::Up => self.player.input.up = false,
-                Keycode::Down => self.player.input.down = false,
-                _ => (),
+    /// Stops recording without saving. Recordings only hold inputs, changes
+    /// made from the console wouldn't play back.
+    fn drop_recording(&mut self) {
+        if self.sim.stop_recording().is_some() {
+            self.console.print("Recording dropped, it can't replay console changes");
+        }
+    }
+
+    /// Rebinds the controls of one player at a time, every press moving on
+    /// to the next one so each can set up their own.
+    fn rebind_next(&mut self) {
+        let players = self.sim.party.slots.len();
+        if players == 0 {
+            return;
+        }
+        let next = self.rebind_slot % players;
+        for (ix, slot) in self.sim.party.slots.iter_mut().enumerate() {
+            let actions: &[Action] = if ix == next { &ACTIONS } else { &[] };
+            slot.input.rebind(actions);
+        }
+        self.rebind_slot = next + 1;
+    }
+
+    fn prompt_rebinding(&mut self) {
+        for (ix, slot) in self.sim.party.slots.iter().enumerate() {
+            if let Some(action) = slot.input.rebinding() {
+                self.console.print(&format!(
+                    "Player {}: press a key or button for {:?}",
+                    ix + 1,
+                    action
+                ));
+            }
+        }
+    }
+}
+
+
+impl event::EventHandler for Game {
+    fn update(&mut self, ctx: &mut Context, dt: Duration) -> GameResult<()> {
+        self.frame_times.push(seconds(&dt));
+
+        if self.toggle_recording {
+            self.toggle_recording = false;
+            self.record_or_save(ctx)?;
+        }
+
+        for command in self.console.take_commands() {
+            match self.run(ctx, command) {
+                Ok(reply) | Err(reply) => self.console.print(&reply),
+            }
+        }
+
+        if self.relayout {
+            self.relayout = false;
+            self.rebuild_views(ctx)?;
+        }
+
+        for slot in self.sim.party.slots.iter_mut() {
+            if slot.input.take_changed() {
+                if let Err(e) = slot.input.bindings().save(ctx) {
+                    self.console.print(&e.to_string());
+                }
+            }
+        }
+
+        while timer::check_update_time(ctx, TICK_RATE) {
+            let paused = self.views
+                .iter_mut()
+                .fold(false, |paused, v| v.camera.effects.pause_tick() || paused);
+            if !paused {
+                self.sim.step(&self.level.terrain);
+                // Right away, a hit pause holds the ticks still to come.
+                for impact in self.sim.take_impacts() {
+                    self.react(ctx, impact)?;
+                }
+            }
+        }
+
+        let bounds = self.level.terrain.bounds();
+        for view in self.views.iter_mut() {
+            view.update(&self.sim.party, seconds(&dt), &bounds);
+        }
+
+        for slot in self.sim.party.slots.iter_mut() {
+            Debug::physics_diagnostics(&mut self.console, &slot.player.mv.diagnostics.drain());
+            for sm in slot.sm.regions_mut() {
+                Debug::state_machine(&mut self.console, sm);
             }
         }
+
+        Ok(())
     }
 
-    fn controller_button_down_event(&mut self, btn: Button, _instance_id: i32) {
-        match btn {
-            Button::A => self.player.input.jump = true,
-            Button::X => self.player.input.attack = true,
-            Button::B => self.player.input.slide = true,
-            Button::LeftShoulder => self.player.mv.position = Vector2::new(300.0, 500.0),
-            _ => (),
+    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        for view in self.views.iter() {
+            view.begin(ctx);
+            self.draw_scene(ctx, &view.camera)?;
         }
 
-        Debug::gamepad_button(btn, _instance_id);
+        graphics::set_canvas(ctx, None);
+        graphics::clear(ctx);
+        for view in self.views.iter() {
+            view.present(ctx)?;
+        }
+        self.hud.draw(ctx, &self.frame_times, &self.sim.party)?;
+        self.console.draw(ctx, self.hud.font())?;
+        graphics::present(ctx);
+
+        Ok(())
     }
-    fn controller_button_up_event(&mut self, _btn: Button, _instance_id: i32) {}
-    fn controller_axis_event(&mut self, axis: Axis, value: i16, _instance_id: i32) {
-        match axis {
-            Axis::LeftX => {
-                if value > 7500 {
-                    self.player.input.right = true
-                } else {
-                    self.player.input.right = false
-                };
-                if value < -7500 {
-                    self.player.input.left = true
-                } else {
-                    self.player.input.left = false
+
+    fn key_down_event(&mut self, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        if self.console.key_down(keycode, repeat) {
+            return;
+        }
+        if !repeat && !Debug::hotkey(keycode, &mut self.console) {
+            match keycode {
+                Keycode::F1 => for slot in self.sim.party.slots.iter() {
+                    self.console.print(&slot.sm.dump());
+                },
+                Keycode::F2 => self.rebind_next(),
+                Keycode::F3 => self.toggle_recording = true,
+                Keycode::F4 => {
+                    self.split_screen = !self.split_screen;
+                    self.relayout = true;
                 }
+                Keycode::F5 => {
+                    self.overview = !self.overview;
+                    self.relayout = true;
+                }
+                _ => self.sim.party.key_down(keycode),
             }
-            Axis::LeftY => if value > 7500 {
-                self.player.input.down = true
-            } else {
-                self.player.input.down = false
-            },
-            _ => (),
+            self.prompt_rebinding();
+        }
+    }
+
+    fn key_up_event(&mut self, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        if !repeat {
+            self.sim.party.key_up(keycode);
         }
+    }
+
+    fn controller_button_down_event(&mut self, btn: Button, instance_id: i32) {
+        self.sim.party.button_down(btn, instance_id);
+        self.prompt_rebinding();
+
+        Debug::gamepad_button(&mut self.console, btn, instance_id);
+    }
+    fn controller_button_up_event(&mut self, btn: Button, instance_id: i32) {
+        self.sim.party.button_up(btn, instance_id);
+    }
+    fn controller_axis_event(&mut self, axis: Axis, value: i16, instance_id: i32) {
+        self.sim.party.axis(axis, value, instance_id);
 
-        Debug::gamepad_axis(axis, value);
+        Debug::gamepad_axis(&mut self.console, &mut self.axis_sides, axis, value, instance_id);
     }
 }
 
@@ -0,0 +1,213 @@
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{Axis, Button, Keycode};
+use serde_json;
+
+use input::{Bindings, Input};
+use physics::{BodyId, MovementProfile};
+use physics::world::World;
+use player::*;
+use state::Regions;
+use Vector2;
+
+/// Which devices drive a player.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum DeviceKind {
+    Keyboard,
+    /// The first gamepad pressing a button that no one else has claimed.
+    Gamepad,
+    /// The keyboard and a gamepad, for playing alone.
+    Any,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlotConfig {
+    pub device: DeviceKind,
+    /// Name of the bindings, see `Bindings::load`.
+    pub bindings: String,
+}
+
+/// Who plays, from `resources/input/players.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PartyConfig {
+    pub players: Vec<SlotConfig>,
+}
+
+impl PartyConfig {
+    pub fn load(ctx: &mut Context) -> GameResult<PartyConfig> {
+        let file = ctx.filesystem.open("/input/players.json")?;
+
+        serde_json::from_reader(file).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken players config: {}", e))
+        })
+    }
+}
+
+/// One player along with everything driving it.
+pub struct Slot {
+    pub player: Player,
+    pub sm: Regions<Player>,
+    pub input: Input,
+    pub device: DeviceKind,
+    /// Instance id of the gamepad this slot claimed.
+    pub gamepad: Option<i32>,
+    pub body: BodyId,
+    /// Where the player comes back after dying.
+    pub checkpoint: Vector2,
+}
+
+impl Slot {
+    pub fn new(
+        player: Player,
+        sm: Regions<Player>,
+        input: Input,
+        device: DeviceKind,
+        body: BodyId,
+        checkpoint: Vector2,
+    ) -> Slot {
+        Slot {
+            player,
+            sm,
+            input,
+            device,
+            gamepad: None,
+            body,
+            checkpoint,
+        }
+    }
+
+    fn takes_keyboard(&self) -> bool {
+        self.device != DeviceKind::Gamepad
+    }
+
+    fn wants_gamepad(&self) -> bool {
+        self.device != DeviceKind::Keyboard && self.gamepad.is_none()
+    }
+
+    pub fn respawn(&mut self) {
+        let at = self.checkpoint;
+        self.teleport(at);
+    }
+
+    /// Puts the player at `at`, falling from there.
+    pub fn teleport(&mut self, at: Vector2) {
+        self.player.respawn(at);
+        self.sm.interrupt(MOVEMENT, PlayerState::Jumping, &mut self.player);
+    }
+
+    /// Knocks the player away from `from`, unless it can't be hurt right
+    /// now. Returns whether it was.
+    pub fn hurt(&mut self, from: Vector2) -> bool {
+        let away = if self.player.mv.position.x < from.x {
+            -1.0
+        } else {
+            1.0
+        };
+        let knockback = Vector2::new(
+            away * self.player.profile.knockback_x,
+            self.player.profile.knockback_y,
+        );
+
+        if self.player.hit(knockback) {
+            self.sm.interrupt(MOVEMENT, PlayerState::Hurt, &mut self.player);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// All local players, routing device events to the ones they belong to.
+pub struct Party {
+    pub slots: Vec<Slot>,
+}
+
+impl Party {
+    /// Spawns a player per configured slot, each at its own spawn point.
+    pub fn new(
+        ctx: &mut Context,
+        config: &PartyConfig,
+        frames: &AnimationFrames,
+        spawns: &[Vector2],
+        world: &mut World,
+    ) -> GameResult<Party> {
+        let profile = MovementProfile::load(ctx, "player")?;
+        let mut slots = vec![];
+
+        for (ix, c) in config.players.iter().enumerate() {
+            let at = Party::spawn_point(spawns, ix);
+            let (player, sm) = Player::new(at, profile.clone(), frames);
+            let input = Input::new(Bindings::load(ctx, &c.bindings)?);
+            slots.push(Slot::new(player, sm, input, c.device, world.add_body(), at));
+        }
+
+        Ok(Party { slots })
+    }
+
+    /// Where the player in slot `ix` starts, players share the spawns in
+    /// turn when there are more of them.
+    pub fn spawn_point(spawns: &[Vector2], ix: usize) -> Vector2 {
+        if spawns.is_empty() {
+            Vector2::new(300.0, 800.0)
+        } else {
+            spawns[ix % spawns.len()]
+        }
+    }
+
+    pub fn slot_of(&self, body: BodyId) -> Option<usize> {
+        self.slots.iter().position(|s| s.body == body)
+    }
+
+    pub fn positions(&self) -> Vec<Vector2> {
+        self.slots.iter().map(|s| s.player.mv.position).collect()
+    }
+
+    pub fn key_down(&mut self, key: Keycode) {
+        for slot in self.slots.iter_mut().filter(|s| s.takes_keyboard()) {
+            slot.input.key_down(key);
+        }
+    }
+
+    pub fn key_up(&mut self, key: Keycode) {
+        for slot in self.slots.iter_mut().filter(|s| s.takes_keyboard()) {
+            slot.input.key_up(key);
+        }
+    }
+
+    /// The slot owning a gamepad.
+    pub fn owner(&self, gamepad: i32) -> Option<usize> {
+        self.slots.iter().position(|s| s.gamepad == Some(gamepad))
+    }
+
+    /// The slot owning a gamepad, handing it to the first slot still
+    /// waiting for one if nobody has it yet.
+    pub fn claim(&mut self, gamepad: i32) -> Option<usize> {
+        if let Some(ix) = self.owner(gamepad) {
+            return Some(ix);
+        }
+
+        let ix = self.slots.iter().position(|s| s.wants_gamepad());
+        if let Some(ix) = ix {
+            self.slots[ix].gamepad = Some(gamepad);
+        }
+        ix
+    }
+
+    pub fn button_down(&mut self, button: Button, gamepad: i32) {
+        if let Some(ix) = self.claim(gamepad) {
+            self.slots[ix].input.button_down(button);
+        }
+    }
+
+    pub fn button_up(&mut self, button: Button, gamepad: i32) {
+        if let Some(ix) = self.owner(gamepad) {
+            self.slots[ix].input.button_up(button);
+        }
+    }
+
+    /// Sticks only count once their gamepad was claimed with a button.
+    pub fn axis(&mut self, axis: Axis, value: i16, gamepad: i32) {
+        if let Some(ix) = self.owner(gamepad) {
+            self.slots[ix].input.axis(axis, value);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use super::*;
+
+/// Air dash bookkeeping: one dash per airtime, with a cooldown between
+/// dashes. Refilled whenever the body touches the ground.
+pub struct Dashing {
+    available: bool,
+    cooldown: f64,
+    remaining: f64,
+}
+
+impl Dashing {
+    pub fn new() -> Dashing {
+        Dashing {
+            available: false,
+            cooldown: 0.0,
+            remaining: 0.0,
+        }
+    }
+
+    pub fn tick(&mut self, mv: &MovingObject, time: &Duration) {
+        self.cooldown = (self.cooldown - seconds(time)).max(0.0);
+        if mv.on_ground {
+            self.available = true;
+        }
+    }
+
+    pub fn can_start(&self) -> bool {
+        self.available && self.cooldown <= 0.0
+    }
+
+    pub fn start(&mut self, profile: &MovementProfile) {
+        self.available = false;
+        self.cooldown = profile.dash_cooldown;
+        self.remaining = profile.dash_time;
+    }
+
+    /// Counts down the running dash, `false` once it's over.
+    pub fn dashing(&mut self, time: &Duration) -> bool {
+        self.remaining -= seconds(time);
+        self.remaining > 0.0
+    }
+}
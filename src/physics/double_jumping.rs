@@ -1,5 +1,5 @@
 use super::moving_object::MovingObject;
-use player::*;
+use super::profile::MovementProfile;
 
 pub struct DoubleJumping {
     available: bool,
@@ -15,14 +15,17 @@ impl DoubleJumping {
         self.available = true;
     }
 
-    pub fn double_jump(&mut self, mv: &mut MovingObject) {
+    pub fn double_jump(&mut self, mv: &mut MovingObject, profile: &MovementProfile) -> bool {
         if self.available {
             // if mv.velocity.y >= 0.0 {
-            //     mv.velocity.y += Player::JUMP_SPEED;
+            //     mv.velocity.y += profile.jump_speed;
             // } else {
-            mv.velocity.y = Player::JUMP_SPEED;
+            mv.velocity.y = profile.jump_speed;
             // }
             self.available = false;
+            true
+        } else {
+            false
         }
     }
 }
@@ -0,0 +1,102 @@
+use super::*;
+
+/// Hitstun and invulnerability after taking a hit. While invulnerable
+/// further hits are ignored and the body blinks.
+pub struct Hurting {
+    stun: f64,
+    invulnerable: f64,
+    blink_interval: f64,
+}
+
+impl Hurting {
+    pub fn new() -> Hurting {
+        Hurting {
+            stun: 0.0,
+            invulnerable: 0.0,
+            blink_interval: 0.0,
+        }
+    }
+
+    pub fn tick(&mut self, time: &Duration) {
+        let dt = seconds(time);
+        self.stun = (self.stun - dt).max(0.0);
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+    }
+
+    /// Knocks the body back unless it's still invulnerable from the last
+    /// hit, `true` if the hit landed.
+    pub fn hit(&mut self, mv: &mut MovingObject, knockback: Vector2, profile: &MovementProfile) -> bool {
+        if self.is_invulnerable() {
+            return false;
+        }
+
+        mv.velocity = Vector2::new(0.0, 0.0);
+        mv.apply_impulse(knockback, profile.hitstun_time);
+
+        self.stun = profile.hitstun_time;
+        self.invulnerable = profile.invulnerability_time;
+        self.blink_interval = profile.blink_interval;
+        true
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.stun > 0.0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable > 0.0
+    }
+
+    /// Whether the body should be drawn this frame.
+    pub fn visible(&self) -> bool {
+        !self.is_invulnerable() || self.blink_interval <= 0.0 ||
+            (self.invulnerable / self.blink_interval) as usize % 2 == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> MovingObject {
+        let position = Vector2::new(0.0, 0.0);
+        let aabb = AABB::new_full(position, Vector2::new(10.0, 10.0), Vector2::new(1.0, 1.0));
+        let mut mv = MovingObject::new(position, aabb);
+        mv.on_ground = true;
+        mv
+    }
+
+    #[test]
+    fn hit_knocks_back_and_locks_control() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut hurt = Hurting::new();
+        mv.velocity = Vector2::new(500.0, 0.0);
+
+        assert!(hurt.hit(&mut mv, Vector2::new(-300.0, 400.0), &profile));
+        assert_eq!(mv.velocity, Vector2::new(-300.0, 400.0));
+        assert!(!mv.on_ground);
+        assert!(mv.is_control_locked());
+        assert!(hurt.is_stunned());
+    }
+
+    #[test]
+    fn invulnerable_until_it_wears_off() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut hurt = Hurting::new();
+
+        assert!(hurt.hit(&mut mv, Vector2::new(0.0, 100.0), &profile));
+        hurt.tick(&Duration::from_millis(
+            (profile.hitstun_time * 1000.0) as u64 + 1,
+        ));
+        assert!(!hurt.is_stunned());
+        assert!(!hurt.hit(&mut mv, Vector2::new(0.0, 100.0), &profile));
+
+        hurt.tick(&Duration::from_millis(
+            (profile.invulnerability_time * 1000.0) as u64,
+        ));
+        assert!(hurt.visible());
+        assert!(hurt.hit(&mut mv, Vector2::new(0.0, 100.0), &profile));
+    }
+}
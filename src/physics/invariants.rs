@@ -0,0 +1,234 @@
+//! Sanity checks run on a body after every physics step.
+//!
+//! Nothing in here panics or prints; broken invariants are collected into
+//! the body's `Diagnostics` queue and it's up to the game to decide what to
+//! do with them (log, draw, assert in tests).
+
+use super::*;
+use level::Terrain;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Position or velocity turned into NaN or infinity.
+    NonFinite { position: Vector2, velocity: Vector2 },
+    /// Body moved through a solid tile in a single step.
+    Tunnelled {
+        from: Vector2,
+        to: Vector2,
+        tile: (isize, isize),
+    },
+    /// Body left the terrain grid.
+    OutOfBounds { position: Vector2 },
+    /// Body moved a lot further than its velocity allows.
+    Teleported { from: Vector2, to: Vector2 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    reports: VecDeque<Violation>,
+    dropped: usize,
+}
+
+impl Diagnostics {
+    const CAPACITY: usize = 64;
+
+    pub fn new() -> Diagnostics {
+        Diagnostics {
+            reports: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn report(&mut self, violation: Violation) {
+        if self.reports.len() >= Diagnostics::CAPACITY {
+            self.reports.pop_front();
+            self.dropped += 1;
+        }
+        self.reports.push_back(violation);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    /// Number of reports thrown away because nobody drained the queue.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn drain(&mut self) -> Vec<Violation> {
+        self.reports.drain(..).collect()
+    }
+}
+
+/// Extra distance a body may cover on top of its velocity in one step,
+/// collision resolution is allowed to snap it by up to a tile.
+const TELEPORT_SLACK_TILES: f64 = 1.0;
+
+pub fn check(mv: &MovingObject, time: &Duration, terrain: &Terrain) -> Vec<Violation> {
+    let mut found = vec![];
+
+    if !is_finite(&mv.position) || !is_finite(&mv.velocity) {
+        found.push(Violation::NonFinite {
+            position: mv.position,
+            velocity: mv.velocity,
+        });
+        return found;
+    }
+
+    if !in_bounds(terrain, &mv.position) {
+        found.push(Violation::OutOfBounds { position: mv.position });
+        return found;
+    }
+
+    let from = mv.old_position + mv.aabb.offset;
+    let to = mv.position + mv.aabb.offset;
+
+    if let Some(tile) = solid_tile_between(terrain, &from, &to) {
+        found.push(Violation::Tunnelled {
+            from: mv.old_position,
+            to: mv.position,
+            tile,
+        });
+    }
+
+    let travelled = (mv.position - mv.old_position).norm();
+    let allowed = mv.old_velocity.norm().max(mv.velocity.norm()) * seconds(time) +
+        terrain.tile_size * TELEPORT_SLACK_TILES;
+    if travelled > allowed {
+        found.push(Violation::Teleported {
+            from: mv.old_position,
+            to: mv.position,
+        });
+    }
+
+    found
+}
+
+fn is_finite(v: &Vector2) -> bool {
+    v.x.is_finite() && v.y.is_finite()
+}
+
+fn in_bounds(terrain: &Terrain, at: &Vector2) -> bool {
+    let half_tile = terrain.tile_size / 2.0;
+    let min = terrain.position - Vector2::new(half_tile, half_tile);
+    let max = min +
+        Vector2::new(
+            terrain.width as f64 * terrain.tile_size,
+            terrain.height as f64 * terrain.tile_size,
+        );
+
+    at.x >= min.x && at.x < max.x && at.y >= min.y && at.y < max.y
+}
+
+/// Walks the segment in quarter tile steps and returns the first solid tile
+/// the segment enters. Tiles the segment starts in are ignored, being stuck
+/// is not the same as tunnelling.
+fn solid_tile_between(terrain: &Terrain, from: &Vector2, to: &Vector2) -> Option<(isize, isize)> {
+    let step = terrain.tile_size / 4.0;
+    let steps = ((to - from).norm() / step).ceil() as usize;
+    let start = (
+        terrain.get_tile_x_at_point(from.x),
+        terrain.get_tile_y_at_point(from.y),
+    );
+
+    for i in 1..steps {
+        let p = lerp(from, to, i as f64 / steps as f64);
+        let tile = (
+            terrain.get_tile_x_at_point(p.x),
+            terrain.get_tile_y_at_point(p.y),
+        );
+        if tile != start && terrain.is_obstacle(tile.0, tile.1) {
+            return Some(tile);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiles are 10 units wide, tile (0, 0) is centered on the origin.
+    fn terrain() -> Terrain {
+        Terrain::from_ascii(&[".....", ".....", "#####", ".....", "#####"], 10.0)
+    }
+
+    fn body(from: Vector2, to: Vector2, velocity: Vector2) -> MovingObject {
+        let aabb = AABB::new_full(to, Vector2::new(4.0, 4.0), Vector2::new(1.0, 1.0));
+        let mut mv = MovingObject::new(to, aabb);
+        mv.old_position = from;
+        mv.velocity = velocity;
+        mv.old_velocity = velocity;
+        mv
+    }
+
+    #[test]
+    fn quiet_step_is_clean() {
+        let mv = body(
+            Vector2::new(20.0, 10.0),
+            Vector2::new(21.0, 10.0),
+            Vector2::new(60.0, 0.0),
+        );
+        assert!(check(&mv, &Duration::from_millis(16), &terrain()).is_empty());
+    }
+
+    #[test]
+    fn detects_nan() {
+        let mv = body(
+            Vector2::new(20.0, 10.0),
+            Vector2::new(::std::f64::NAN, 10.0),
+            Vector2::new(0.0, 0.0),
+        );
+        match check(&mv, &Duration::from_millis(16), &terrain()).first() {
+            Some(&Violation::NonFinite { .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detects_tunnelling_through_floor() {
+        let mv = body(
+            Vector2::new(20.0, 30.0),
+            Vector2::new(20.0, 10.0),
+            Vector2::new(0.0, -1500.0),
+        );
+        let found = check(&mv, &Duration::from_millis(16), &terrain());
+        assert_eq!(
+            found,
+            vec![
+                Violation::Tunnelled {
+                    from: Vector2::new(20.0, 30.0),
+                    to: Vector2::new(20.0, 10.0),
+                    tile: (2, 2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_out_of_bounds() {
+        let mv = body(
+            Vector2::new(20.0, 10.0),
+            Vector2::new(20.0, -100.0),
+            Vector2::new(0.0, -10000.0),
+        );
+        assert_eq!(
+            check(&mv, &Duration::from_millis(16), &terrain()),
+            vec![Violation::OutOfBounds { position: Vector2::new(20.0, -100.0) }]
+        );
+    }
+
+    #[test]
+    fn diagnostics_are_bounded() {
+        let mut d = Diagnostics::new();
+        for _ in 0..Diagnostics::CAPACITY + 3 {
+            d.report(Violation::OutOfBounds { position: Vector2::new(0.0, 0.0) });
+        }
+        assert_eq!(d.dropped(), 3);
+        assert_eq!(d.drain().len(), Diagnostics::CAPACITY);
+        assert!(d.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use super::*;
+
+/// Keeps jumping forgiving: a short coyote window after walking off a ledge,
+/// a buffer replaying presses made just before landing and cutting the jump
+/// short when the button is released early.
+///
+/// All windows are in seconds and come from the `MovementProfile`.
+#[derive(Debug, Clone)]
+pub struct JumpAssist {
+    buffered: f64,
+    coyote: bool,
+    can_cut: bool,
+}
+
+impl JumpAssist {
+    pub fn new() -> JumpAssist {
+        JumpAssist {
+            buffered: 0.0,
+            coyote: false,
+            can_cut: false,
+        }
+    }
+
+    /// Advances the timers, call once per physics step after the body moved.
+    pub fn tick(&mut self, mv: &MovingObject, time: &Duration) {
+        self.buffered = (self.buffered - seconds(time)).max(0.0);
+
+        if mv.on_ground {
+            self.coyote = true;
+        }
+        if mv.velocity.y <= 0.0 {
+            self.can_cut = false;
+        }
+    }
+
+    /// Jumps off the ground, a ledge or during the coyote window.
+    pub fn jump(&mut self, mv: &mut MovingObject, profile: &MovementProfile) {
+        mv.velocity.y = profile.jump_speed;
+        self.jumped();
+    }
+
+    /// Records a jump that happened some other way, e.g. a double jump, so it
+    /// can be cut and doesn't leave a stale coyote window or buffer around.
+    pub fn jumped(&mut self) {
+        self.coyote = false;
+        self.can_cut = true;
+        self.buffered = 0.0;
+    }
+
+    pub fn can_coyote_jump(&self, mv: &MovingObject, profile: &MovementProfile) -> bool {
+        self.coyote && mv.time_off_ground <= profile.coyote_time && mv.velocity.y <= 0.0 &&
+            !mv.at_ceiling
+    }
+
+    /// Remembers a press that couldn't be used right away.
+    pub fn buffer(&mut self, profile: &MovementProfile) {
+        self.buffered = profile.jump_buffer_time;
+    }
+
+    /// Whether a press was buffered within the last `jump_buffer_time`
+    /// seconds. Jumping uses it up.
+    pub fn has_buffered(&self) -> bool {
+        self.buffered > 0.0
+    }
+
+    /// Cuts the rise short once the jump button is let go.
+    pub fn cut(&mut self, mv: &mut MovingObject, profile: &MovementProfile, held: bool) {
+        if self.can_cut && !held && mv.velocity.y > 0.0 {
+            mv.velocity.y *= profile.jump_cut;
+            self.can_cut = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> MovingObject {
+        let position = Vector2::new(0.0, 0.0);
+        let aabb = AABB::new_full(position, Vector2::new(10.0, 10.0), Vector2::new(1.0, 1.0));
+        MovingObject::new(position, aabb)
+    }
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn buffered_press_expires() {
+        let profile = MovementProfile::default();
+        let mv = body();
+        let mut ja = JumpAssist::new();
+
+        ja.buffer(&profile);
+        ja.tick(&mv, &millis((profile.jump_buffer_time * 1000.0) as u64 + 1));
+        assert!(!ja.has_buffered());
+    }
+
+    #[test]
+    fn buffered_press_is_replayed_once() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut ja = JumpAssist::new();
+
+        ja.buffer(&profile);
+        ja.tick(&mv, &millis(10));
+        assert!(ja.has_buffered());
+        ja.jump(&mut mv, &profile);
+        assert!(!ja.has_buffered());
+    }
+
+    #[test]
+    fn coyote_window_after_walking_off() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut ja = JumpAssist::new();
+
+        mv.on_ground = true;
+        ja.tick(&mv, &millis(16));
+
+        mv.on_ground = false;
+        mv.time_off_ground = profile.coyote_time / 2.0;
+        assert!(ja.can_coyote_jump(&mv, &profile));
+
+        mv.time_off_ground = profile.coyote_time * 2.0;
+        assert!(!ja.can_coyote_jump(&mv, &profile));
+    }
+
+    #[test]
+    fn no_coyote_jump_after_jumping() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut ja = JumpAssist::new();
+
+        mv.on_ground = true;
+        ja.tick(&mv, &millis(16));
+        ja.jump(&mut mv, &profile);
+        mv.on_ground = false;
+        mv.velocity.y = -1.0;
+
+        assert!(!ja.can_coyote_jump(&mv, &profile));
+    }
+
+    #[test]
+    fn releasing_cuts_jump_once() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut ja = JumpAssist::new();
+
+        ja.jump(&mut mv, &profile);
+        ja.cut(&mut mv, &profile, true);
+        assert_eq!(mv.velocity.y, profile.jump_speed);
+
+        ja.cut(&mut mv, &profile, false);
+        assert_eq!(mv.velocity.y, profile.jump_speed * profile.jump_cut);
+
+        ja.cut(&mut mv, &profile, false);
+        assert_eq!(mv.velocity.y, profile.jump_speed * profile.jump_cut);
+    }
+
+    #[test]
+    fn falling_jump_is_not_cut() {
+        let profile = MovementProfile::default();
+        let mut mv = body();
+        let mut ja = JumpAssist::new();
+
+        ja.jump(&mut mv, &profile);
+        mv.velocity.y = -100.0;
+        ja.tick(&mv, &millis(16));
+        ja.cut(&mut mv, &profile, false);
+        assert_eq!(mv.velocity.y, -100.0);
+    }
+}
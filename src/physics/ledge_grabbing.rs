@@ -1,24 +1,38 @@
 use super::*;
-use level::Terrain;
+use level::{Terrain, TileFilter};
 use player::PlayerInput;
+use super::profile::MovementProfile;
 
 pub struct LedgeGrabbing {
     pub ledge_tile: (isize, isize),
+    /// `1.0` when the ledge is to the right of the body, `-1.0` when it's to
+    /// the left.
+    pub side: f64,
+    climb_from: Vector2,
+    climb_to: Vector2,
+    climb_progress: f64,
+    /// How far the body has been pushed towards the next tile while the
+    /// hands can't slide there.
+    travel: f64,
 }
 
 impl LedgeGrabbing {
-    const GRAB_LEDGE_START_Y: f64 = 0.0;
-    const GRAB_LEDGE_END_Y: f64 = 2.0;
-    const GRAB_LEDGE_TILE_OFFSET: f64 = -4.0;
-
     pub fn new() -> LedgeGrabbing {
-        LedgeGrabbing { ledge_tile: (0, 0) }
+        LedgeGrabbing {
+            ledge_tile: (0, 0),
+            side: 1.0,
+            climb_from: Vector2::new(0.0, 0.0),
+            climb_to: Vector2::new(0.0, 0.0),
+            climb_progress: 0.0,
+            travel: 0.0,
+        }
     }
 
     pub fn grab_ledge(
         &mut self,
         mv: &mut MovingObject,
         pi: &PlayerInput,
+        profile: &MovementProfile,
         terrain: &Terrain,
     ) -> bool {
         if mv.velocity.y <= 0.0 && !mv.at_ceiling && (pi.right && mv.pushes_right_wall) ||
@@ -42,20 +56,20 @@ impl LedgeGrabbing {
             {
                 top_y = terrain.get_tile_y_at_point(
                     mv.old_position.y + mv.aabb.offset.y + aabb_corner_offset.y -
-                        LedgeGrabbing::GRAB_LEDGE_TILE_OFFSET,
+                        profile.grab_ledge_tile_offset,
                 );
                 bottom_y = terrain.get_tile_y_at_point(
                     mv.aabb.center.y + aabb_corner_offset.y -
-                        LedgeGrabbing::GRAB_LEDGE_TILE_OFFSET,
+                        profile.grab_ledge_tile_offset,
                 );
             } else {
                 top_y = terrain.get_tile_y_at_point(
                     mv.aabb.center.y + aabb_corner_offset.y -
-                        LedgeGrabbing::GRAB_LEDGE_TILE_OFFSET,
+                        profile.grab_ledge_tile_offset,
                 );
                 bottom_y = terrain.get_tile_y_at_point(
                     mv.aabb.center.y + aabb_corner_offset.y -
-                        LedgeGrabbing::GRAB_LEDGE_END_Y,
+                        profile.grab_ledge_end_y,
                 );
             };
 
@@ -68,23 +82,312 @@ impl LedgeGrabbing {
 
                     if y > bottom_y ||
                         (mv.aabb.center.y + aabb_corner_offset.y) - tile_corner.y <=
-                            LedgeGrabbing::GRAB_LEDGE_END_Y &&
+                            profile.grab_ledge_end_y &&
                             tile_corner.y - (mv.aabb.center.y + aabb_corner_offset.y) >=
-                                LedgeGrabbing::GRAB_LEDGE_START_Y
+                                profile.grab_ledge_start_y
                     {
                         self.ledge_tile = (tile_x, y - 1);
+                        self.side = aabb_corner_offset.x.signum();
                         mv.position.y = tile_corner.y - aabb_corner_offset.y - mv.aabb.offset.y -
-                            LedgeGrabbing::GRAB_LEDGE_START_Y +
-                            LedgeGrabbing::GRAB_LEDGE_TILE_OFFSET;
+                            profile.grab_ledge_start_y +
+                            profile.grab_ledge_tile_offset;
 
                         mv.velocity = Vector2::new(mv.velocity.x / 2.0, 0.0);
+                        self.travel = 0.0;
                         return true;
                     }
                 };
             }
             return false;
+        } else if profile.grab_one_way_ledges && mv.velocity.y <= 0.0 && (pi.left ^ pi.right) {
+            let side = if pi.right { 1.0 } else { -1.0 };
+            self.grab_one_way_ledge(mv, side, profile, terrain)
         } else {
             false
         }
     }
+
+    /// Catches the end of a one-way platform the hands passed by this step.
+    fn grab_one_way_ledge(
+        &mut self,
+        mv: &mut MovingObject,
+        side: f64,
+        profile: &MovementProfile,
+        terrain: &Terrain,
+    ) -> bool {
+        let hand_x = mv.aabb.center.x + side * (mv.aabb.half_size.x + 1.0);
+        let top = mv.aabb.center.y + mv.aabb.half_size.y;
+        let old_top = mv.old_position.y + mv.aabb.offset.y + mv.aabb.half_size.y;
+
+        let tile_x = terrain.tile_floor_x(hand_x);
+        let near_x = tile_x - side as isize;
+
+        for y in terrain.tile_floor_y(top)..terrain.tile_floor_y(old_top) + 1 {
+            if !terrain.is_one_way_platform(tile_x, y) || !terrain.is_empty(tile_x, y + 1) ||
+                terrain.is_ground(near_x, y)
+            {
+                continue;
+            }
+
+            let (min, max) = terrain.tile_bounds(tile_x, y);
+            if top > max.y + profile.grab_ledge_end_y ||
+                old_top - profile.grab_ledge_tile_offset < max.y
+            {
+                continue;
+            }
+
+            self.ledge_tile = (tile_x, y);
+            self.side = side;
+            self.travel = 0.0;
+            mv.position = hang_position(mv, (tile_x, y), side, profile, terrain);
+            mv.velocity = Vector2::new(0.0, 0.0);
+            mv.aabb.center = mv.position + mv.aabb.offset;
+            return true;
+        }
+        false
+    }
+
+    fn is_ledge(&self, x: isize, y: isize, profile: &MovementProfile, terrain: &Terrain) -> bool {
+        let grabbable = terrain.is_obstacle(x, y) ||
+            (profile.grab_one_way_ledges && terrain.is_one_way_platform(x, y));
+        grabbable && terrain.is_empty(x, y + 1)
+    }
+
+    /// Moves the hanging body sideways. The hands slide along a run of
+    /// grabbable tiles while the body doesn't run into anything; where the
+    /// run ends the hands move over to the next grabbable tile, a step up or
+    /// down included, once the body has pushed a tile's width towards it.
+    /// Returns `false` when there's nowhere to go.
+    pub fn shimmy(
+        &mut self,
+        mv: &mut MovingObject,
+        dx: f64,
+        profile: &MovementProfile,
+        terrain: &Terrain,
+    ) -> bool {
+        let x = mv.position.x + dx;
+        let center = Vector2::new(x + mv.aabb.offset.x, mv.aabb.center.y);
+        let hand_x = center.x + self.side * (mv.aabb.half_size.x + 1.0);
+        let tile_x = terrain.tile_floor_x(hand_x);
+
+        if self.is_ledge(tile_x, self.ledge_tile.1, profile, terrain) &&
+            terrain.is_rect_free(center, mv.aabb.half_size, TileFilter::Solid)
+        {
+            self.ledge_tile.0 = tile_x;
+            self.travel = 0.0;
+            mv.position.x = x;
+            mv.aabb.center = center;
+            return true;
+        }
+
+        let (next, at) = match self.next_ledge(mv, dx.signum(), profile, terrain) {
+            Some(next) => next,
+            None => {
+                self.travel = 0.0;
+                return false;
+            }
+        };
+
+        self.travel += dx.abs();
+        if self.travel >= terrain.tile_size {
+            self.travel = 0.0;
+            self.ledge_tile = next;
+            mv.position = at;
+            mv.old_position = at;
+            mv.aabb.center = at + mv.aabb.offset;
+        }
+        true
+    }
+
+    /// The grabbable tile next to the held one in direction `dir`, level
+    /// with it or a step up or down, and where the body would hang from it.
+    fn next_ledge(
+        &self,
+        mv: &MovingObject,
+        dir: f64,
+        profile: &MovementProfile,
+        terrain: &Terrain,
+    ) -> Option<((isize, isize), Vector2)> {
+        let (x, y) = self.ledge_tile;
+        let next_x = x + dir as isize;
+
+        for &dy in [0, 1, -1].iter() {
+            let tile = (next_x, y + dy);
+            if !self.is_ledge(tile.0, tile.1, profile, terrain) {
+                continue;
+            }
+
+            let at = hang_position(mv, tile, self.side, profile, terrain);
+            if terrain.is_rect_free(at + mv.aabb.offset, mv.aabb.half_size, TileFilter::Solid) {
+                return Some((tile, at));
+            }
+        }
+        None
+    }
+
+    /// Where the body would stand on top of the grabbed tile, `None` when
+    /// there is no room for it there.
+    pub fn climb_target(&self, mv: &MovingObject, terrain: &Terrain) -> Option<Vector2> {
+        let (min, max) = terrain.tile_bounds(self.ledge_tile.0, self.ledge_tile.1);
+        let edge_x = if self.side > 0.0 { min.x } else { max.x };
+
+        let target = Vector2::new(
+            edge_x + self.side * (mv.aabb.half_size.x + 1.0) - mv.aabb.offset.x,
+            max.y + mv.aabb.half_size.y - mv.aabb.offset.y,
+        );
+
+        if terrain.is_rect_free(target + mv.aabb.offset, mv.aabb.half_size, TileFilter::Solid) {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    pub fn start_climb(&mut self, mv: &MovingObject, target: Vector2) {
+        self.climb_from = mv.position;
+        self.climb_to = target;
+        self.climb_progress = 0.0;
+    }
+
+    /// Pulls the body up first and then over the edge. Returns `true` once
+    /// it stands on the ledge.
+    pub fn climb(&mut self, mv: &mut MovingObject, time: &Duration, profile: &MovementProfile) -> bool {
+        self.climb_progress =
+            (self.climb_progress + seconds(time) / profile.ledge_climb_time.max(0.001)).min(1.0);
+
+        let up = (self.climb_progress * 2.0).min(1.0);
+        let over = (self.climb_progress * 2.0 - 1.0).max(0.0);
+
+        mv.old_position = mv.position;
+        mv.position = Vector2::new(
+            self.climb_from.x + (self.climb_to.x - self.climb_from.x) * over,
+            self.climb_from.y + (self.climb_to.y - self.climb_from.y) * up,
+        );
+        mv.velocity = Vector2::new(0.0, 0.0);
+        mv.accel = Vector2::new(0.0, 0.0);
+        mv.aabb.center = mv.position + mv.aabb.offset;
+
+        if self.climb_progress >= 1.0 {
+            mv.on_ground = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Where the body hangs with its hands on `tile`, the tile being on `side`
+/// of it.
+fn hang_position(
+    mv: &MovingObject,
+    tile: (isize, isize),
+    side: f64,
+    profile: &MovementProfile,
+    terrain: &Terrain,
+) -> Vector2 {
+    let (min, max) = terrain.tile_bounds(tile.0, tile.1);
+    let edge_x = if side > 0.0 { min.x } else { max.x };
+
+    Vector2::new(
+        edge_x - side * mv.aabb.half_size.x - mv.aabb.offset.x,
+        max.y - mv.aabb.half_size.y - mv.aabb.offset.y - profile.grab_ledge_start_y +
+            profile.grab_ledge_tile_offset,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiles are 10 units wide and tile (0, 0) is centered on the origin.
+    // The body is 6 by 4, short enough to hang from a step.
+    fn body(at: Vector2) -> MovingObject {
+        let aabb = AABB::new_full(at, Vector2::new(6.0, 4.0), Vector2::new(1.0, 1.0));
+        MovingObject::new(at, aabb)
+    }
+
+    fn stairs() -> Terrain {
+        Terrain::from_ascii(
+            &[
+                "......",
+                ".....#",
+                "....##",
+                "....##",
+                "######",
+            ],
+            10.0,
+        )
+    }
+
+    fn hang(
+        lg: &mut LedgeGrabbing,
+        mv: &mut MovingObject,
+        tile: (isize, isize),
+        terrain: &Terrain,
+    ) {
+        let at = hang_position(mv, tile, 1.0, &MovementProfile::default(), terrain);
+        lg.ledge_tile = tile;
+        lg.side = 1.0;
+        mv.position = at;
+        mv.aabb.center = at + mv.aabb.offset;
+    }
+
+    #[test]
+    fn grabs_the_top_of_a_wall_and_climbs() {
+        let t = stairs();
+        let profile = MovementProfile::default();
+        let mut lg = LedgeGrabbing::new();
+        let mut mv = body(Vector2::new(32.0, 20.0));
+        mv.pushes_right_wall = true;
+        let mut input = PlayerInput::new();
+        input.right = true;
+
+        assert!(lg.grab_ledge(&mut mv, &input, &profile, &t));
+        assert_eq!(lg.ledge_tile, (4, 2));
+        assert_eq!(lg.side, 1.0);
+        assert_eq!(mv.position.y, 19.0);
+
+        assert_eq!(lg.climb_target(&mv, &t), Some(Vector2::new(39.0, 27.0)));
+    }
+
+    #[test]
+    fn shimmies_up_a_step() {
+        let t = stairs();
+        let profile = MovementProfile::default();
+        let mut lg = LedgeGrabbing::new();
+        let mut mv = body(Vector2::new(0.0, 0.0));
+        hang(&mut lg, &mut mv, (4, 2), &t);
+        assert_eq!(mv.position, Vector2::new(32.0, 19.0));
+
+        // The wall is in the way, the body pushes until it's a tile's worth.
+        assert!(lg.shimmy(&mut mv, 4.0, &profile, &t));
+        assert!(lg.shimmy(&mut mv, 4.0, &profile, &t));
+        assert_eq!(lg.ledge_tile, (4, 2));
+        assert!(lg.shimmy(&mut mv, 4.0, &profile, &t));
+        assert_eq!(lg.ledge_tile, (5, 3));
+        assert_eq!(mv.position, Vector2::new(42.0, 29.0));
+
+        // Nothing further along.
+        assert!(!lg.shimmy(&mut mv, 4.0, &profile, &t));
+    }
+
+    #[test]
+    fn shimmies_along_a_platform_to_its_end() {
+        let t = Terrain::from_ascii(
+            &["..........", "...====...", "..........", "##########"],
+            10.0,
+        );
+        let profile = MovementProfile::default();
+        let mut lg = LedgeGrabbing::new();
+        let mut mv = body(Vector2::new(0.0, 0.0));
+        hang(&mut lg, &mut mv, (3, 2), &t);
+        assert_eq!(mv.position, Vector2::new(22.0, 19.0));
+
+        // Letting go on the open side.
+        assert!(!lg.shimmy(&mut mv, -5.0, &profile, &t));
+
+        while lg.shimmy(&mut mv, 5.0, &profile, &t) {}
+        assert_eq!(lg.ledge_tile, (6, 2));
+        assert_eq!(mv.position.x, 57.0);
+    }
 }
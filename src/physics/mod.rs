@@ -2,13 +2,24 @@ mod aabb;
 mod moving_object;
 mod ledge_grabbing;
 mod double_jumping;
-mod quad_tree;
+mod profile;
+mod jump_assist;
+mod dashing;
+mod hurting;
+mod trigger;
+pub mod quad_tree;
+pub mod invariants;
 pub mod world;
 
 pub use self::aabb::*;
 pub use self::moving_object::*;
 pub use self::ledge_grabbing::*;
 pub use self::double_jumping::*;
+pub use self::profile::*;
+pub use self::jump_assist::*;
+pub use self::dashing::*;
+pub use self::hurting::*;
+pub use self::trigger::*;
 
 use super::Vector2;
 use std::time::Duration;
@@ -20,9 +31,3 @@ pub fn seconds(dur: &Duration) -> f64 {
 pub fn lerp(v1: &Vector2, v2: &Vector2, by: f64) -> Vector2 {
     v1 * (1.0 - by) + v2 * by
 }
-
-pub fn round_vector(mut v: Vector2) -> Vector2 {
-    v.x = v.x.round();
-    v.y = v.y.round();
-    v
-}
@@ -1,8 +1,17 @@
 use super::*;
-use level::Terrain;
+use level::{Terrain, TileFilter};
 use std::time::Duration;
-use std::cmp;
-use debug::Debug;
+use super::invariants::{self, Diagnostics, Violation};
+
+/// The boxes a body looks into for surfaces it rests against, as center
+/// and half size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probes {
+    pub ground: (Vector2, Vector2),
+    pub ceiling: (Vector2, Vector2),
+    pub left: (Vector2, Vector2),
+    pub right: (Vector2, Vector2),
+}
 
 #[derive(Debug, Clone)]
 pub struct MovingObject {
@@ -34,10 +43,29 @@ pub struct MovingObject {
     pub cannot_go_left_frames: usize,
     pub cannot_go_right_frames: usize,
 
-    pub frames_from_jump_start: isize,
+    /// Seconds since the body last stood on the ground.
+    pub time_off_ground: f64,
+
+    pub platform_threshold: f64,
+
+    /// Seconds left during which the body ignores steering, set by
+    /// impulses.
+    pub control_lock: f64,
+
+    pub diagnostics: Diagnostics,
 }
 
 impl MovingObject {
+    /// Longest distance covered by a single substep, in tiles.
+    const SUBSTEP_TILES: f64 = 0.5;
+    const MAX_SUBSTEPS: usize = 16;
+    /// Contacts resolved per substep, enough for a floor and a wall.
+    const MAX_CONTACTS: usize = 3;
+    /// How far past the box the contact probes reach.
+    const PROBE_REACH: f64 = 2.0;
+    /// How much narrower than the box the probes are.
+    const PROBE_INSET: f64 = 2.0;
+
     pub fn new(position: Vector2, aabb: AABB) -> MovingObject {
         MovingObject {
             old_position: position.clone(),
@@ -58,10 +86,38 @@ impl MovingObject {
             at_ceiling: false,
             cannot_go_left_frames: 0,
             cannot_go_right_frames: 0,
-            frames_from_jump_start: 0,
+            time_off_ground: 0.0,
+            platform_threshold: 2.0,
+            control_lock: 0.0,
+            diagnostics: Diagnostics::new(),
         }
     }
 
+    /// Kicks the body with an instant change of velocity and takes control
+    /// away from it for `lock` seconds. An upward kick lifts it off the
+    /// ground right away.
+    pub fn apply_impulse(&mut self, impulse: Vector2, lock: f64) {
+        self.velocity += impulse;
+        self.accel = Vector2::new(0.0, 0.0);
+        self.control_lock = self.control_lock.max(lock);
+
+        if impulse.y > 0.0 {
+            self.on_ground = false;
+            self.on_platform = false;
+        }
+    }
+
+    pub fn is_control_locked(&self) -> bool {
+        self.control_lock > 0.0
+    }
+
+    /// Integrates velocity and moves the body through the tile grid.
+    ///
+    /// Long steps are split into substeps no longer than half a tile and each
+    /// substep sweeps the box against the terrain, always resolving the
+    /// earliest contact first and sliding along it with the remaining
+    /// motion. Contacts the body merely rests against are picked up by short
+    /// probes afterwards.
     pub fn update_physics(&mut self, time: &Duration, terrain: &Terrain) {
         self.old_position = self.position;
         self.old_velocity = self.velocity;
@@ -72,233 +128,329 @@ impl MovingObject {
         self.pushed_left_wall = self.pushes_left_wall;
         self.pushed_right_wall = self.pushes_right_wall;
 
-        self.velocity += self.accel * seconds(time);
-        self.position += self.velocity * seconds(time);
+        self.on_ground = false;
+        self.on_platform = false;
+        self.at_ceiling = false;
+        self.pushes_left_wall = false;
+        self.pushes_right_wall = false;
 
-        let mut ground_y = 0.0;
-        let mut ceiling_y = 0.0;
-        let mut right_wall_x = 0.0;
-        let mut left_wall_x = 0.0;
+        let dt = seconds(time);
+        self.control_lock = (self.control_lock - dt).max(0.0);
 
-        self.on_platform = false;
+        let substeps = self.substeps(dt, terrain);
+        let h = dt / substeps as f64;
+
+        for _ in 0..substeps {
+            self.velocity += self.accel * h;
+            let delta = self.velocity * h;
+            self.sweep(delta, terrain);
+        }
+
+        self.probe_contacts(terrain);
 
-        if self.velocity.y <= 0.0 && self.has_ground(&mut ground_y, terrain) {
-            self.position.y = ground_y + self.aabb.half_size.y - self.aabb.offset.y;
-            self.velocity.y = 0.0;
-            self.on_ground = true;
+        if self.on_ground {
+            self.time_off_ground = 0.0;
         } else {
-            self.on_ground = false;
+            self.time_off_ground += dt;
         }
 
-        if self.velocity.x <= 0.0 && self.collides_with_left_wall(&mut left_wall_x, terrain) {
-            if self.old_position.x - self.aabb.half_size.x + self.aabb.offset.x >= left_wall_x {
-                self.position.x = left_wall_x + self.aabb.half_size.x - self.aabb.offset.x;
-                self.pushes_left_wall = true;
-            };
-            self.velocity.x = self.velocity.x.max(0.0);
-            self.accel.x = self.accel.x.max(0.0);
+        self.aabb.center = self.position + self.aabb.offset;
+
+        self.check_invariants(time, terrain);
+    }
+
+    fn substeps(&self, dt: f64, terrain: &Terrain) -> usize {
+        let reach = (self.velocity + self.accel * dt) * dt;
+        let longest = reach.x.abs().max(reach.y.abs());
+        let steps = (longest / (terrain.tile_size * MovingObject::SUBSTEP_TILES)).ceil();
+
+        if steps.is_finite() {
+            (steps as usize).max(1).min(MovingObject::MAX_SUBSTEPS)
         } else {
-            self.pushes_left_wall = false;
+            1
         }
+    }
 
-        if self.velocity.x >= 0.0 && self.collides_with_right_wall(&mut right_wall_x, terrain) {
-            if self.old_position.x + self.aabb.half_size.x + self.aabb.offset.x <= right_wall_x {
-                self.position.x = right_wall_x - self.aabb.half_size.x - self.aabb.offset.x;
-                self.pushes_right_wall = true;
+    fn sweep(&mut self, mut delta: Vector2, terrain: &Terrain) {
+        for _ in 0..MovingObject::MAX_CONTACTS {
+            if delta.x == 0.0 && delta.y == 0.0 {
+                return;
             }
+
+            let center = self.position + self.aabb.offset;
+            let hit = terrain.box_cast(center, self.aabb.half_size, delta, self.platform_threshold);
+
+            match hit {
+                None => {
+                    self.position += delta;
+                    return;
+                }
+                Some(hit) => {
+                    self.position = hit.center - self.aabb.offset;
+                    let remaining = delta * (1.0 - hit.time);
+
+                    if hit.normal.x != 0.0 {
+                        self.touch_wall(hit.normal.x);
+                        delta = Vector2::new(0.0, remaining.y);
+                    } else {
+                        if hit.normal.y > 0.0 {
+                            self.on_ground = true;
+                            self.on_platform = terrain.is_one_way_platform(hit.tile.0, hit.tile.1);
+                        } else {
+                            self.at_ceiling = true;
+                        }
+                        self.velocity.y = 0.0;
+                        delta = Vector2::new(remaining.x, 0.0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn touch_wall(&mut self, normal_x: f64) {
+        if normal_x > 0.0 {
+            self.pushes_left_wall = true;
+            self.velocity.x = self.velocity.x.max(0.0);
+            self.accel.x = self.accel.x.max(0.0);
+        } else {
+            self.pushes_right_wall = true;
             self.velocity.x = self.velocity.x.min(0.0);
             self.accel.x = self.accel.x.min(0.0);
-        } else {
-            self.pushes_right_wall = false;
         }
+    }
 
+    /// Thin boxes a couple of units past each side of the box, the floor
+    /// and ceiling ones a bit narrower than it so floors don't count as
+    /// walls and walls don't count as floors.
+    pub fn probes(&self) -> Probes {
+        let hs = self.aabb.half_size;
+        let center = self.position + self.aabb.offset;
+        let inset = MovingObject::PROBE_INSET;
+        let reach = MovingObject::PROBE_REACH;
 
-        if self.velocity.y >= 0.0 && self.has_ceiling(&mut ceiling_y, terrain) {
-            self.position.y = ceiling_y - self.aabb.half_size.y - self.aabb.offset.y - 1.0;
-            self.velocity.y = 0.0;
-            self.at_ceiling = true;
-        } else {
-            self.at_ceiling = false;
+        let level_hs = Vector2::new(hs.x - inset, reach / 2.0);
+        let side_hs = Vector2::new(reach / 2.0, hs.y - inset);
+
+        Probes {
+            ground: (Vector2::new(center.x, center.y - hs.y - reach / 2.0), level_hs),
+            ceiling: (Vector2::new(center.x, center.y + hs.y + reach / 2.0), level_hs),
+            left: (Vector2::new(center.x - hs.x - reach / 2.0, center.y), side_hs),
+            right: (Vector2::new(center.x + hs.x + reach / 2.0, center.y), side_hs),
         }
+    }
 
-        self.aabb.center = self.position + self.aabb.offset;
+    /// Looks into the probes for surfaces the body rests against without
+    /// moving into them.
+    fn probe_contacts(&mut self, terrain: &Terrain) {
+        let probes = self.probes();
+        let hs = self.aabb.half_size;
+        let bottom = self.position.y + self.aabb.offset.y - hs.y;
+        let reach = MovingObject::PROBE_REACH;
 
-        Debug::detect_teleporting(&self);
-    }
+        if self.velocity.y <= 0.0 && !self.on_ground {
+            let (probe, probe_hs) = probes.ground;
+
+            // The highest tile the body lands on holds it up, solid tiles
+            // over platforms at the same height.
+            let mut landing: Option<(f64, bool)> = None;
+            for (x, y) in terrain.overlap_rect(probe, probe_hs, TileFilter::Ground) {
+                let tile_top = terrain.tile_bounds(x, y).1.y;
+                let one_way = terrain.is_one_way_platform(x, y);
+                let lands = if one_way {
+                    bottom >= tile_top - self.platform_threshold
+                } else {
+                    tile_top <= bottom + reach
+                };
 
-    pub fn has_ground(&mut self, ground_y: &mut f64, terrain: &Terrain) -> bool {
-        let old_bottom_left = self.aabb
-            .sensor(&self.old_position, Sensor::BottomLeft)
-            .right()
-            .down()
-            .ok();
-        let new_bottom_left = self.aabb
-            .sensor(&self.position, Sensor::BottomLeft)
-            .right()
-            .down()
-            .ok();
-        let end_y = terrain.get_tile_y_at_point(new_bottom_left.y);
-        let beg_y = cmp::max(terrain.get_tile_y_at_point(old_bottom_left.y) - 1, end_y);
-        let dist = cmp::max((end_y - beg_y).abs(), 1);
-        let mut tile_index_x;
-        for tile_index_y in (end_y..beg_y + 1).rev() {
-            let bottom_left = lerp(
-                &new_bottom_left,
-                &old_bottom_left,
-                (end_y - tile_index_y).abs() as f64 / dist as f64,
-            );
-
-            let bottom_right =
-                Vector2::new(bottom_left.x + self.aabb.half_size.x * 2.0, bottom_left.y)
-                    .left()
-                    .left();
-            let mut checked_tile = bottom_left.clone();
-            'inner: loop {
-                checked_tile.x = checked_tile.x.min(bottom_right.x);
-                tile_index_x = terrain.get_tile_x_at_point(checked_tile.x);
-                *ground_y = tile_index_y as f64 * terrain.tile_size + terrain.tile_size / 2.0 +
-                    terrain.position.y;
-                if terrain.is_obstacle(tile_index_x, tile_index_y) {
-                    self.on_platform = false;
-                    return true;
-                } else if terrain.is_one_way_platform(tile_index_x, tile_index_y) &&
-                    (checked_tile.y - *ground_y).abs() <=
-                        MovingObject::PLATFORM_THRESHOLD + self.old_position.y - self.position.y
-                {
-                    self.on_platform = true;
+                let higher = match landing {
+                    Some((top, was_one_way)) => {
+                        tile_top > top || (tile_top == top && was_one_way && !one_way)
+                    }
+                    None => true,
                 };
-                if checked_tile.x >= bottom_right.x {
-                    if self.on_platform {
-                        return true;
-                    };
-                    break 'inner;
+                if lands && higher {
+                    landing = Some((tile_top, one_way));
                 }
-                checked_tile.x += terrain.tile_size;
+            }
+
+            if let Some((tile_top, one_way)) = landing {
+                self.on_platform = one_way;
+                self.on_ground = true;
+                self.position.y = tile_top + hs.y - self.aabb.offset.y;
+                self.velocity.y = 0.0;
             }
         }
-        false
-    }
 
-    pub fn has_ceiling(&self, ceiling_y: &mut f64, terrain: &Terrain) -> bool {
-        *ceiling_y = 0.0;
-        let old_top_right = self.aabb
-            .sensor(&self.old_position, Sensor::TopRight)
-            .left()
-            .up()
-            .ok();
-        let new_top_right = self.aabb
-            .sensor(&self.position, Sensor::TopRight)
-            .left()
-            .up()
-            .ok();
-        let end_y = terrain.get_tile_y_at_point(new_top_right.y);
-        let beg_y = cmp::min(terrain.get_tile_y_at_point(old_top_right.y) + 1, end_y);
-        let dist = cmp::max((end_y - beg_y).abs(), 1);
-        let mut tile_index_x;
-        for tile_index_y in beg_y..end_y + 1 {
-            let top_right = lerp(
-                &new_top_right,
-                &old_top_right,
-                ((end_y - tile_index_y).abs() as f64 / dist as f64),
-            );
-            let top_left = Vector2::new(top_right.x - self.aabb.half_size.x * 2.0, top_right.y)
-                .right()
-                .right();
-            let mut checked_tile = top_left.clone();
-            loop {
-                checked_tile.x = checked_tile.x.min(top_right.x);
-                tile_index_x = terrain.get_tile_x_at_point(checked_tile.x);
-                if terrain.is_obstacle(tile_index_x, tile_index_y) {
-                    *ceiling_y = tile_index_y as f64 * terrain.tile_size - terrain.tile_size / 2.0 +
-                        terrain.position.y;
-                    return true;
-                }
-                if checked_tile.x >= top_right.x {
-                    break;
-                }
-                checked_tile.x += terrain.tile_size;
+        if self.velocity.y >= 0.0 && !self.at_ceiling {
+            let (probe, probe_hs) = probes.ceiling;
+            if !terrain.is_rect_free(probe, probe_hs, TileFilter::Solid) {
+                self.at_ceiling = true;
             }
         }
-        false
-    }
 
-    pub fn collides_with_left_wall(&self, wall_x: &mut f64, terrain: &Terrain) -> bool {
-        *wall_x = 0.0;
-        let old_bottom_left = self.aabb
-            .sensor(&self.old_position, Sensor::BottomLeft)
-            .left()
-            .ok();
-        let new_bottom_left = self.aabb
-            .sensor(&self.position, Sensor::BottomLeft)
-            .left()
-            .ok();
-        let mut tile_index_y;
-        let end_x = terrain.get_tile_x_at_point(new_bottom_left.x);
-        let beg_x = cmp::max(terrain.get_tile_x_at_point(old_bottom_left.x) - 1, end_x);
-        let dist = cmp::max((end_x - beg_x).abs(), 1);
-        for tile_index_x in (end_x..beg_x + 1).rev() {
-            let bottom_left = lerp(
-                &new_bottom_left,
-                &old_bottom_left,
-                (end_x - tile_index_x).abs() as f64 / dist as f64,
-            );
-            let top_left = bottom_left + Vector2::new(0.0, self.aabb.half_size.y * 2.0);
-            let mut checked_tile = bottom_left;
-            loop {
-                checked_tile.y = checked_tile.y.min(top_left.y);
-                tile_index_y = terrain.get_tile_y_at_point(checked_tile.y);
-                if terrain.is_obstacle(tile_index_x, tile_index_y) {
-                    *wall_x = tile_index_x as f64 * terrain.tile_size + terrain.tile_size / 2.0 +
-                        terrain.position.x;
-                    return true;
-                }
-                if checked_tile.y >= top_left.y {
-                    break;
-                }
-                checked_tile.y += terrain.tile_size;
+        if self.velocity.x <= 0.0 && !self.pushes_left_wall {
+            let (probe, probe_hs) = probes.left;
+            if !terrain.is_rect_free(probe, probe_hs, TileFilter::Solid) {
+                self.touch_wall(1.0);
+            }
+        }
+
+        if self.velocity.x >= 0.0 && !self.pushes_right_wall {
+            let (probe, probe_hs) = probes.right;
+            if !terrain.is_rect_free(probe, probe_hs, TileFilter::Solid) {
+                self.touch_wall(-1.0);
             }
         }
-        false
     }
 
-    pub fn collides_with_right_wall(&self, wall_x: &mut f64, terrain: &Terrain) -> bool {
-        *wall_x = 0.0;
-        let old_bottom_right = self.aabb
-            .sensor(&self.old_position, Sensor::BottomRight)
-            .right()
-            .ok();
-        let new_bottom_right = self.aabb
-            .sensor(&self.position, Sensor::BottomRight)
-            .right()
-            .ok();
-        let end_x = terrain.get_tile_x_at_point(new_bottom_right.x);
-        let beg_x = cmp::min(terrain.get_tile_x_at_point(old_bottom_right.x) + 1, end_x);
-        let dist = cmp::max((end_x - beg_x).abs(), 1);
-        let mut tile_index_y;
-        for tile_index_x in beg_x..end_x + 1 {
-            let bottom_right = lerp(
-                &new_bottom_right,
-                &old_bottom_right,
-                (end_x - tile_index_x).abs() as f64 / dist as f64,
-            );
-            let top_right = bottom_right + Vector2::new(0.0, self.aabb.half_size.y * 2.0);
-            let mut checked_tile = bottom_right;
-            loop {
-                checked_tile.y = checked_tile.y.min(top_right.y);
-                tile_index_y = terrain.get_tile_y_at_point(checked_tile.y);
-                if terrain.is_obstacle(tile_index_x, tile_index_y) {
-                    *wall_x = tile_index_x as f64 * terrain.tile_size - terrain.tile_size / 2.0 +
-                        terrain.position.x;
-                    return true;
-                }
-                if checked_tile.y >= top_right.y {
-                    break;
-                }
-                checked_tile.y += terrain.tile_size;
+    fn check_invariants(&mut self, time: &Duration, terrain: &Terrain) {
+        for violation in invariants::check(self, time, terrain) {
+            if let Violation::NonFinite { .. } = violation {
+                self.position = self.old_position;
+                self.velocity = Vector2::new(0.0, 0.0);
+                self.accel = Vector2::new(0.0, 0.0);
+                self.aabb.center = self.position + self.aabb.offset;
             }
+            self.diagnostics.report(violation);
         }
-        false
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiles are 10 units wide, tile (0, 0) is centered on the origin and
+    // floors at y = 0 have their top at y = 5.
+    fn body(at: Vector2, velocity: Vector2) -> MovingObject {
+        let aabb = AABB::new_full(at, Vector2::new(6.0, 8.0), Vector2::new(1.0, 1.0));
+        let mut mv = MovingObject::new(at, aabb);
+        mv.velocity = velocity;
+        mv
+    }
+
+    fn step(mv: &mut MovingObject, terrain: &Terrain, ms: u64) {
+        mv.update_physics(&Duration::from_millis(ms), terrain);
+        assert!(mv.diagnostics.is_empty(), "{:?}", mv.diagnostics.drain());
+    }
+
+    fn well() -> Terrain {
+        Terrain::from_ascii(
+            &[
+                "#......#",
+                "#......#",
+                "#......#",
+                "#......#",
+                "#......#",
+                "#......#",
+                "#......#",
+                "#......#",
+                "#..==..#",
+                "#......#",
+                "#......#",
+                "########",
+            ],
+            10.0,
+        )
+    }
+
+    #[test]
+    fn high_speed_fall_lands_on_floor() {
+        let t = well();
+        let mut mv = body(Vector2::new(20.0, 100.0), Vector2::new(0.0, -4000.0));
+
+        step(&mut mv, &t, 500);
+
+        assert!(mv.on_ground);
+        assert!(!mv.on_platform);
+        assert_eq!(mv.position, Vector2::new(20.0, 9.0));
+        assert_eq!(mv.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn high_speed_fall_lands_on_platform() {
+        let t = well();
+        let mut mv = body(Vector2::new(35.0, 100.0), Vector2::new(0.0, -4000.0));
+
+        step(&mut mv, &t, 500);
+
+        assert!(mv.on_ground);
+        assert!(mv.on_platform);
+        assert_eq!(mv.position, Vector2::new(35.0, 39.0));
+    }
+
+    #[test]
+    fn jumping_through_platform_from_below() {
+        let t = well();
+        let mut mv = body(Vector2::new(35.0, 9.0), Vector2::new(0.0, 80.0));
+
+        step(&mut mv, &t, 500);
+
+        assert!(!mv.on_ground);
+        assert_eq!(mv.position, Vector2::new(35.0, 49.0));
+    }
+
+    #[test]
+    fn diagonal_corner_entry_lands_on_top() {
+        let t = Terrain::from_ascii(&["......", "......", "...###", "...###"], 10.0);
+        let mut mv = body(Vector2::new(12.0, 29.0), Vector2::new(40.0, -40.0));
 
-    pub const PLATFORM_THRESHOLD: f64 = 2.0;
+        step(&mut mv, &t, 500);
+
+        assert!(mv.on_ground);
+        assert!(!mv.pushes_right_wall);
+        assert_eq!(mv.position, Vector2::new(32.0, 19.0));
+    }
+
+    #[test]
+    fn diagonal_entry_into_wall_slides_down() {
+        let t = Terrain::from_ascii(&["....#", "....#", "....#", "....#", "#####"], 10.0);
+        let mut mv = body(Vector2::new(17.0, 30.0), Vector2::new(40.0, -20.0));
+
+        step(&mut mv, &t, 500);
+
+        assert!(mv.pushes_right_wall);
+        assert!(!mv.on_ground);
+        assert_eq!(mv.position, Vector2::new(32.0, 20.0));
+    }
+
+    #[test]
+    fn fast_body_stops_at_wall() {
+        let t = well();
+        let mut mv = body(Vector2::new(20.0, 9.0), Vector2::new(10000.0, 0.0));
+
+        step(&mut mv, &t, 100);
+
+        assert!(mv.pushes_right_wall);
+        assert!(mv.on_ground);
+        assert_eq!(mv.position, Vector2::new(62.0, 9.0));
+        assert_eq!(mv.velocity.x, 0.0);
+    }
+
+    #[test]
+    fn resting_contacts_are_probed() {
+        let t = well();
+        let mut mv = body(Vector2::new(8.0, 9.0), Vector2::new(0.0, 0.0));
+
+        step(&mut mv, &t, 16);
+
+        assert!(mv.on_ground);
+        assert!(mv.pushes_left_wall);
+        assert!(!mv.pushes_right_wall);
+        assert!(!mv.at_ceiling);
+    }
+
+    #[test]
+    fn probe_rests_on_the_highest_tile() {
+        // Tiles 2 units wide, a step with its top at y = 3 next to a floor
+        // with its top at y = 1, the probe reaches down over both.
+        let t = Terrain::from_ascii(&[".#..", "####"], 2.0);
+        let mut mv = body(Vector2::new(3.0, 6.5), Vector2::new(0.0, 0.0));
+
+        mv.probe_contacts(&t);
+
+        assert!(mv.on_ground);
+        assert!(!mv.on_platform);
+        assert_eq!(mv.position, Vector2::new(3.0, 7.0));
+    }
 }
@@ -0,0 +1,130 @@
+use ggez::{Context, GameError, GameResult};
+use serde_json;
+
+use std::io::Read;
+
+/// Tunable movement feel of a character, loaded from
+/// `resources/profiles/<name>.json`. Missing fields fall back to the
+/// defaults below.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MovementProfile {
+    pub gravity: f64,
+    pub max_falling_speed: f64,
+    pub jump_speed: f64,
+    pub walk_speed: f64,
+    pub walk_accel: f64,
+
+    /// Seconds after walking off a ledge during which jumping still works.
+    pub coyote_time: f64,
+    /// Seconds a jump pressed in the air is remembered for landing.
+    pub jump_buffer_time: f64,
+    /// Upward speed kept when the jump button is released mid-rise.
+    pub jump_cut: f64,
+
+    /// How far below the top of a one-way platform feet may sink and still
+    /// land on it.
+    pub platform_threshold: f64,
+
+    /// Braking on the ground, as a multiple of `walk_accel`.
+    pub ground_friction: f64,
+    /// Fraction of the horizontal speed shed per second in the air when not
+    /// steering.
+    pub air_drag: f64,
+    /// How much of the ground acceleration is available in the air.
+    pub air_control: f64,
+
+    /// Fastest fall while sliding down a wall.
+    pub wall_slide_speed: f64,
+    pub wall_jump_x: f64,
+    pub wall_jump_y: f64,
+    /// Frames after a wall jump during which steering back to the wall is
+    /// ignored.
+    pub wall_jump_lockout_frames: usize,
+
+    pub grab_ledge_start_y: f64,
+    pub grab_ledge_end_y: f64,
+    pub grab_ledge_tile_offset: f64,
+    /// Horizontal speed a slide starts with.
+    pub slide_speed: f64,
+    /// Deceleration while sliding.
+    pub slide_friction: f64,
+    /// Speed at which a slide ends, or crawls on under a low ceiling.
+    pub slide_stop_speed: f64,
+    /// Hitbox height while sliding, relative to standing.
+    pub slide_height: f64,
+
+    pub dash_speed: f64,
+    pub dash_time: f64,
+    pub dash_cooldown: f64,
+
+    /// Seconds it takes to pull up onto a ledge.
+    pub ledge_climb_time: f64,
+    pub ledge_shimmy_speed: f64,
+    /// Whether the ends of one-way platforms can be grabbed too.
+    pub grab_one_way_ledges: bool,
+
+    /// Seconds without control after getting hit.
+    pub hitstun_time: f64,
+    /// Seconds after getting hit during which further hits are ignored.
+    pub invulnerability_time: f64,
+    /// Seconds between blinks while invulnerable.
+    pub blink_interval: f64,
+    /// Speed hazards knock the body away with.
+    pub knockback_x: f64,
+    pub knockback_y: f64,
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        MovementProfile {
+            gravity: -3000.0,
+            max_falling_speed: -4000.0,
+            jump_speed: 1600.0,
+            walk_speed: 1100.0,
+            walk_accel: 700.0,
+            coyote_time: 0.13,
+            jump_buffer_time: 0.1,
+            jump_cut: 0.5,
+            platform_threshold: 2.0,
+            ground_friction: 5.0,
+            air_drag: 1.0 / 3.0,
+            air_control: 1.0,
+            wall_slide_speed: 500.0,
+            wall_jump_x: 900.0,
+            wall_jump_y: 1500.0,
+            wall_jump_lockout_frames: 8,
+            grab_ledge_start_y: 0.0,
+            grab_ledge_end_y: 2.0,
+            grab_ledge_tile_offset: -4.0,
+            slide_speed: 1600.0,
+            slide_friction: 2500.0,
+            slide_stop_speed: 300.0,
+            slide_height: 0.45,
+            dash_speed: 2000.0,
+            dash_time: 0.18,
+            dash_cooldown: 0.5,
+            ledge_climb_time: 0.35,
+            ledge_shimmy_speed: 250.0,
+            grab_one_way_ledges: true,
+            hitstun_time: 0.35,
+            invulnerability_time: 1.2,
+            blink_interval: 0.08,
+            knockback_x: 900.0,
+            knockback_y: 900.0,
+        }
+    }
+}
+
+impl MovementProfile {
+    pub fn load(ctx: &mut Context, name: &str) -> GameResult<MovementProfile> {
+        let file = ctx.filesystem.open(format!("/profiles/{}.json", name))?;
+        MovementProfile::from_reader(file, name)
+    }
+
+    pub fn from_reader<R: Read>(reader: R, name: &str) -> GameResult<MovementProfile> {
+        serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken movement profile {}: {}", name, e))
+        })
+    }
+}
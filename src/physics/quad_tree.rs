@@ -30,6 +30,7 @@ where
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.nodes = None;
     }
 
     fn split(&mut self) {
@@ -41,31 +42,6 @@ where
 
         let level = self.level + 1;
 
-        self.nodes = Some(RefCell::new(Box::new(
-            [
-                QuadTree::new(
-                    level,
-                    Rect::new(x + sub_width, y, sub_width, sub_height),
-                ),
-                QuadTree::new(
-                    level,
-                    Rect::new(x, y, sub_width, sub_height),
-                ),
-                QuadTree::new(
-                    level,
-                    Rect::new(x, y + sub_height, sub_width, sub_height),
-                ),
-                QuadTree::new(
-                    level,
-                    Rect::new(
-                        x + sub_width,
-                        y + sub_height,
-                        sub_width,
-                        sub_height,
-                    ),
-                ),
-            ],
-        )));
         self.nodes = Some(RefCell::new(Box::new([
             QuadTree::new(level, Rect::new(x + sub_width, y, sub_width, sub_height)),
             QuadTree::new(level, Rect::new(x, y, sub_width, sub_height)),
@@ -93,23 +69,26 @@ where
                 self.split();
             }
 
-            for o in self.objects.drain(..) {
+            let objects: Vec<&'a T> = self.objects.drain(..).collect();
+            for o in objects {
                 let ix = get_index(&self.bounds, &o.to_rect());
 
-                if ix != -1 {
-                    if let Some(ref nodes) = self.nodes {
-                        nodes.borrow_mut()[ix as usize].insert(o);
-                    }
+                match self.nodes {
+                    Some(ref nodes) if ix != -1 => nodes.borrow_mut()[ix as usize].insert(o),
+                    _ => self.objects.push(o),
                 }
             }
         }
     }
 
-    fn retreive_rec(&self, ret: &mut Vec<&'a T>, rect: Rect) {
-        let ix = get_index(&self.bounds, &rect);
-        if ix != -1 {
-            if let Some(ref nodes) = self.nodes {
-                nodes.borrow()[ix as usize].retreive_rec(ret, rect);
+    fn retreive_rec(&self, ret: &mut Vec<&'a T>, rect: &Rect) {
+        if let Some(ref nodes) = self.nodes {
+            let nodes = nodes.borrow();
+            match get_index(&self.bounds, rect) {
+                -1 => for node in nodes.iter() {
+                    node.retreive_rec(ret, rect);
+                },
+                ix => nodes[ix as usize].retreive_rec(ret, rect),
             }
         }
 
@@ -118,12 +97,23 @@ where
 
     pub fn retrieve(&self, rect: Rect) -> Vec<&'a T> {
         let mut ret = vec![];
-        self.retreive_rec(&mut ret, rect);
+        self.retreive_rec(&mut ret, &rect);
+        ret
+    }
+
+    /// Bounds of this node and all below it.
+    pub fn node_bounds(&self) -> Vec<Rect> {
+        let mut ret = vec![self.bounds.clone()];
+        if let Some(ref nodes) = self.nodes {
+            for node in nodes.borrow().iter() {
+                ret.extend(node.node_bounds());
+            }
+        }
         ret
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rect {
     pub x: f64,
     pub y: f64,
@@ -172,3 +162,32 @@ fn get_index(bounds: &Rect, rect: &Rect) -> isize {
 
 const MAX_OBJECTS: usize = 10;
 const MAX_LEVELS: usize = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Dot(f64, f64);
+
+    impl Positioned for Dot {
+        fn to_rect(&self) -> Rect {
+            Rect::new(self.0, self.1, 1.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn straddling_query_sees_every_quadrant() {
+        let dots: Vec<Dot> = (0..MAX_OBJECTS * 2)
+            .map(|i| if i % 2 == 0 { Dot(10.0, 10.0) } else { Dot(80.0, 80.0) })
+            .chain(Some(Dot(49.5, 49.5)))
+            .collect();
+
+        let mut qt = QuadTree::new(0, Rect::new(0.0, 0.0, 100.0, 100.0));
+        for d in dots.iter() {
+            qt.insert(d);
+        }
+
+        assert_eq!(qt.retrieve(Rect::new(40.0, 40.0, 20.0, 20.0)).len(), dots.len());
+        assert_eq!(qt.retrieve(Rect::new(5.0, 5.0, 10.0, 10.0)).len(), MAX_OBJECTS + 1);
+    }
+}
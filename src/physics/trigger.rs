@@ -0,0 +1,85 @@
+use super::*;
+use super::quad_tree::{Positioned, Rect};
+
+/// Handle of a trigger registered in the `World`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TriggerId(usize);
+
+/// Handle of a body tracked by the `World`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BodyId(usize);
+
+impl TriggerId {
+    pub fn new(id: usize) -> TriggerId {
+        TriggerId(id)
+    }
+}
+
+impl BodyId {
+    pub fn new(id: usize) -> BodyId {
+        BodyId(id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum TriggerKind {
+    Checkpoint,
+    LevelExit,
+    CameraZone,
+    /// Kills whatever touches it, e.g. a pit below the level.
+    KillPlane,
+    /// Hurts and knocks back whatever touches it, e.g. spikes.
+    Hazard,
+    /// Anything else, identified by the trigger name.
+    Custom,
+}
+
+/// An axis aligned region that reports bodies entering, staying in and
+/// leaving it. Triggers never push bodies around.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pub name: String,
+    pub kind: TriggerKind,
+    pub center: Vector2,
+    pub half_size: Vector2,
+}
+
+impl Trigger {
+    pub fn new(name: &str, kind: TriggerKind, center: Vector2, half_size: Vector2) -> Trigger {
+        Trigger {
+            name: name.to_owned(),
+            kind,
+            center,
+            half_size,
+        }
+    }
+
+    /// Whether the trigger overlaps a body, touching edges don't count.
+    pub fn overlaps(&self, mv: &MovingObject) -> bool {
+        let d = self.center - (mv.position + mv.aabb.offset);
+        let reach = self.half_size + mv.aabb.half_size;
+        d.x.abs() < reach.x && d.y.abs() < reach.y
+    }
+}
+
+impl Positioned for (TriggerId, Trigger) {
+    fn to_rect(&self) -> Rect {
+        let xy = self.1.center - self.1.half_size;
+        let wh = self.1.half_size * 2.0;
+        Rect::new(xy.x, xy.y, wh.x, wh.y)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Contact {
+    Enter,
+    Stay,
+    Exit,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TriggerEvent {
+    pub trigger: TriggerId,
+    pub body: BodyId,
+    pub contact: Contact,
+}
@@ -1,28 +1,203 @@
 use super::*;
-use camera::Camera;
-use player::Player;
-use self::quad_tree::*;
+use super::quad_tree::{Positioned, QuadTree, Rect};
+use level::Terrain;
 
+use std::collections::BTreeSet;
+
+/// Everything in the level that isn't terrain but still takes part in
+/// physics. For now those are trigger volumes, checked against the bodies
+/// handed in every step.
 pub struct World {
-    players: Vec<Player>,
+    bounds: Rect,
+    triggers: Vec<(TriggerId, Trigger)>,
+    next_trigger: usize,
+    next_body: usize,
+    overlaps: BTreeSet<(TriggerId, BodyId)>,
 }
 
 impl World {
-    pub fn new() -> World {
-        World { players: vec![] }
+    pub fn new(terrain: &Terrain) -> World {
+        World {
+            bounds: terrain.bounds(),
+            triggers: vec![],
+            next_trigger: 0,
+            next_body: 0,
+            overlaps: BTreeSet::new(),
+        }
+    }
+
+    pub fn add_body(&mut self) -> BodyId {
+        self.next_body += 1;
+        BodyId::new(self.next_body - 1)
+    }
+
+    pub fn add_trigger(&mut self, trigger: Trigger) -> TriggerId {
+        let id = TriggerId::new(self.next_trigger);
+        self.next_trigger += 1;
+        self.triggers.push((id, trigger));
+        id
+    }
+
+    /// Removes a trigger, bodies still inside it get an exit event on the
+    /// next step.
+    pub fn remove_trigger(&mut self, id: TriggerId) -> Option<Trigger> {
+        match self.triggers.iter().position(|&(tid, _)| tid == id) {
+            Some(ix) => Some(self.triggers.remove(ix).1),
+            None => None,
+        }
+    }
+
+    pub fn trigger(&self, id: TriggerId) -> Option<&Trigger> {
+        self.triggers
+            .iter()
+            .find(|&&(tid, _)| tid == id)
+            .map(|&(_, ref t)| t)
+    }
+
+    pub fn triggers(&self) -> &[(TriggerId, Trigger)] {
+        &self.triggers
+    }
+
+    /// Triggers `body` was inside of after the last step.
+    pub fn overlapping(&self, body: BodyId) -> Vec<TriggerId> {
+        self.overlaps
+            .iter()
+            .filter(|&&(_, b)| b == body)
+            .map(|&(t, _)| t)
+            .collect()
+    }
+
+    /// How the triggers get split up for lookups, for debugging.
+    pub fn trigger_nodes(&self) -> Vec<Rect> {
+        let mut tree = QuadTree::new(0, self.bounds.clone());
+        for entry in self.triggers.iter() {
+            tree.insert(entry);
+        }
+        tree.node_bounds()
+    }
+
+    /// Checks every body against the triggers and reports what changed
+    /// since the last step. Bodies left out of `bodies` count as gone and
+    /// leave all their triggers.
+    pub fn update_triggers(&mut self, bodies: &[(BodyId, &MovingObject)]) -> Vec<TriggerEvent> {
+        let mut current = BTreeSet::new();
+
+        {
+            let mut tree = QuadTree::new(0, self.bounds.clone());
+            for entry in self.triggers.iter() {
+                tree.insert(entry);
+            }
+
+            for &(body, mv) in bodies.iter() {
+                for &&(id, ref trigger) in tree.retrieve(mv.to_rect()).iter() {
+                    if trigger.overlaps(mv) {
+                        current.insert((id, body));
+                    }
+                }
+            }
+        }
+
+        let mut events = vec![];
+
+        for &(trigger, body) in self.overlaps.difference(&current) {
+            events.push(TriggerEvent {
+                trigger,
+                body,
+                contact: Contact::Exit,
+            });
+        }
+
+        for &(trigger, body) in current.iter() {
+            let contact = if self.overlaps.contains(&(trigger, body)) {
+                Contact::Stay
+            } else {
+                Contact::Enter
+            };
+            events.push(TriggerEvent {
+                trigger,
+                body,
+                contact,
+            });
+        }
+
+        self.overlaps = current;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        World::new(&Terrain::from_ascii(&["..........", "..........", "##########"], 10.0))
     }
 
-    pub fn add_player(&mut self, p: Player) {
-        self.players.push(p);
+    fn body(at: Vector2) -> MovingObject {
+        let aabb = AABB::new_full(at, Vector2::new(4.0, 4.0), Vector2::new(1.0, 1.0));
+        MovingObject::new(at, aabb)
     }
 
-    pub fn update_areas(&mut self, camera: &Camera) {
-        let vs = camera.size();
-        let vc = camera.location();
+    fn contacts(events: &[TriggerEvent]) -> Vec<Contact> {
+        events.iter().map(|e| e.contact).collect()
+    }
+
+    #[test]
+    fn enter_stay_exit() {
+        let mut w = world();
+        let b = w.add_body();
+        let t = w.add_trigger(Trigger::new(
+            "exit",
+            TriggerKind::LevelExit,
+            Vector2::new(50.0, 10.0),
+            Vector2::new(5.0, 5.0),
+        ));
+
+        let mut mv = body(Vector2::new(20.0, 10.0));
+        assert!(w.update_triggers(&[(b, &mv)]).is_empty());
+
+        mv.position = Vector2::new(45.0, 10.0);
+        let events = w.update_triggers(&[(b, &mv)]);
+        assert_eq!(
+            events,
+            vec![
+                TriggerEvent {
+                    trigger: t,
+                    body: b,
+                    contact: Contact::Enter,
+                },
+            ]
+        );
+
+        assert_eq!(contacts(&w.update_triggers(&[(b, &mv)])), vec![Contact::Stay]);
+
+        mv.position = Vector2::new(60.0, 10.0);
+        assert_eq!(contacts(&w.update_triggers(&[(b, &mv)])), vec![Contact::Exit]);
+    }
+
+    #[test]
+    fn removed_trigger_and_body_exit() {
+        let mut w = world();
+        let a = w.add_body();
+        let b = w.add_body();
+        let zone = Trigger::new(
+            "zone",
+            TriggerKind::CameraZone,
+            Vector2::new(50.0, 10.0),
+            Vector2::new(50.0, 10.0),
+        );
+        let t = w.add_trigger(zone);
+
+        let mv = body(Vector2::new(30.0, 10.0));
+        assert_eq!(
+            contacts(&w.update_triggers(&[(a, &mv), (b, &mv)])),
+            vec![Contact::Enter, Contact::Enter]
+        );
 
-        let xy = vc - vs * 0.666;
+        assert_eq!(contacts(&w.update_triggers(&[(a, &mv)])), vec![Contact::Exit, Contact::Stay]);
 
-        let rect = quad_tree::Rect::new(xy.x, xy.y, vs.x * 1.5, vs.y * 1.5);
-        // let qt = quad_tree::QuadTree::new(0, rect);
+        assert!(w.remove_trigger(t).is_some());
+        assert!(w.trigger(t).is_none());
+        assert_eq!(contacts(&w.update_triggers(&[(a, &mv)])), vec![Contact::Exit]);
     }
 }
@@ -1,19 +1,34 @@
 use state::*;
 
-use ggez::{Context, GameResult};
-
 use std::boxed::Box;
 use std::time::Duration;
 
-use ggez::graphics::*;
-
-use super::camera::*;
 use super::physics::*;
-use super::level::Terrain;
-use super::debug;
-use sprite::Loader;
+use super::level::{Terrain, TileFilter};
+use input::{Action, Input};
 use sprite::animation::Animated;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlayerState {
+    Idle,
+    Running,
+    Jumping,
+    Sliding,
+    LedgeGrab,
+    LedgeClimb,
+    WallSlide,
+    AirDash,
+    Hurt,
+    /// Action layer: hands free.
+    Ready,
+    Attacking,
+}
+
+/// Region of the player's state machine that moves the body.
+pub const MOVEMENT: usize = 0;
+/// Region that runs attacks on top of whatever the body is doing.
+pub const ACTION: usize = 1;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Direction {
     Left,
@@ -21,48 +36,71 @@ pub enum Direction {
 }
 
 pub struct Player {
-    data: PlayerData,
+    pub anim: PlayerAnimations,
     pub input: PlayerInput,
     pub direction: Direction,
     pub mv: MovingObject,
     pub lg: LedgeGrabbing,
     pub dj: DoubleJumping,
+    pub ja: JumpAssist,
+    pub dash: Dashing,
+    pub hurt: Hurting,
+    pub profile: MovementProfile,
+    standing_scale: Vector2,
+    /// Left crouched by a slide that ended under a low ceiling, stands up
+    /// once there's room.
+    stuck_crouching: bool,
 }
 
 impl Player {
-    pub fn new(ctx: &mut Context) -> GameResult<(Player, StateMachine)> {
-        let scale = 0.4;
-
-        let data = PlayerData::new(ctx, scale)?;
-
-        let player_size = Vector2::new(290.0, 500.0) * scale;
-        let aabb = AABB::new_full(
-            Vector2::new(300.0, 800.0),
-            player_size,
-            Vector2::new(0.7, 0.8),
-        );
+    /// Drawn at this fraction of the sprite size, the hitbox follows.
+    pub const SCALE: f64 = 0.4;
+
+    pub fn new(
+        at: Vector2,
+        profile: MovementProfile,
+        frames: &AnimationFrames,
+    ) -> (Player, Regions<Player>) {
+        let player_size = Vector2::new(290.0, 500.0) * Player::SCALE;
+        let aabb_scale = Vector2::new(0.7, 0.8);
+        let aabb = AABB::new_full(at, player_size, aabb_scale);
 
         let mut p = Player {
-            data,
-            input: PlayerInput {
-                left: false,
-                right: false,
-                down: false,
-                up: false,
-                slide: false,
-                jump: false,
-                attack: false,
-            },
+            anim: PlayerAnimations::new(frames),
+            input: PlayerInput::new(),
             direction: Direction::Right,
-            mv: MovingObject::new(Vector2::new(300.0, 800.0), aabb),
+            mv: MovingObject::new(at, aabb),
             lg: LedgeGrabbing::new(),
             dj: DoubleJumping::new(),
+            ja: JumpAssist::new(),
+            dash: Dashing::new(),
+            hurt: Hurting::new(),
+            profile,
+            standing_scale: aabb_scale,
+            stuck_crouching: false,
         };
-
-        let mut sm = StateMachine::new(Idle);
+        p.mv.platform_threshold = p.profile.platform_threshold;
+
+        // Idle, a grounded state and the one it pushed is as deep as it gets.
+        let movement = StateMachine::new(PlayerState::Idle)
+            .with_rules(rules())
+            .with_max_depth(3);
+        let action = StateMachine::new(PlayerState::Ready)
+            .with_rules(action_rules())
+            .with_max_depth(2);
+        let mut sm = Regions::new(vec![movement, action]).with_conflicts(conflicts());
         sm.start(&mut p);
 
-        Ok((p, sm))
+        (p, sm)
+    }
+
+    /// Puts the player back at `at`, standing still.
+    pub fn respawn(&mut self, at: Vector2) {
+        self.mv.position = at;
+        self.mv.old_position = at;
+        self.mv.velocity = Vector2::new(0.0, 0.0);
+        self.mv.accel = Vector2::new(0.0, 0.0);
+        self.mv.aabb.center = at + self.mv.aabb.offset;
     }
 
     pub fn direct(&mut self) {
@@ -76,117 +114,306 @@ impl Player {
     }
 
     pub fn movement(&mut self) {
+        if self.mv.is_control_locked() {
+            return;
+        }
+
+        let control = if self.mv.on_ground {
+            1.0
+        } else {
+            self.profile.air_control
+        };
+        let accel = self.profile.walk_accel * control;
+        let speed = self.profile.walk_speed;
+
         match self.direction {
             Direction::Left => {
                 if self.mv.pushes_left_wall {
                     self.stop();
                 } else {
-                    self.mv.accel.x = -Player::WALK_ACCEL;
-                    self.mv.velocity.x = (-Player::WALK_SPEED / 2.0).min(self.mv.velocity.x).max(
-                        -Player::WALK_SPEED,
-                    );
+                    self.mv.accel.x = -accel;
+                    self.mv.velocity.x = (-speed / 2.0 * control)
+                        .min(self.mv.velocity.x)
+                        .max(-speed);
                 }
             }
             Direction::Right => {
                 if self.mv.pushes_right_wall {
                     self.stop();
                 } else {
-                    self.mv.accel.x = Player::WALK_ACCEL;
-                    self.mv.velocity.x = (Player::WALK_SPEED / 2.0).max(self.mv.velocity.x).min(
-                        Player::WALK_SPEED,
-                    );
+                    self.mv.accel.x = accel;
+                    self.mv.velocity.x = (speed / 2.0 * control)
+                        .max(self.mv.velocity.x)
+                        .min(speed);
                 }
             }
         }
     }
 
+    pub fn update_physics(&mut self, duration: &Duration, terrain: &Terrain) {
+        self.mv.update_physics(duration, terrain);
+        self.ja.tick(&self.mv, duration);
+        self.dash.tick(&self.mv, duration);
+        self.hurt.tick(duration);
+
+        if self.stuck_crouching && self.can_stand(terrain) {
+            self.stand();
+        }
+    }
+
+    /// Knocks the player back, `true` if it wasn't invulnerable and should
+    /// go into `Hurt`.
+    pub fn hit(&mut self, knockback: Vector2) -> bool {
+        if knockback.x != 0.0 {
+            self.direction = if knockback.x > 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+        }
+        self.hurt.hit(&mut self.mv, knockback, &self.profile)
+    }
+
+    pub fn facing(&self) -> f64 {
+        match self.direction {
+            Direction::Left => -1.0,
+            Direction::Right => 1.0,
+        }
+    }
+
+    /// Shrinks the hitbox for sliding.
+    pub fn crouch(&mut self) {
+        let scale = Vector2::new(
+            self.standing_scale.x,
+            self.standing_scale.y * self.profile.slide_height,
+        );
+        self.mv.aabb.rescale(scale);
+        self.mv.aabb.center = self.mv.position + self.mv.aabb.offset;
+    }
+
+    pub fn stand(&mut self) {
+        self.stuck_crouching = false;
+        self.mv.aabb.rescale(self.standing_scale);
+        self.mv.aabb.center = self.mv.position + self.mv.aabb.offset;
+    }
+
+    /// Whether the standing hitbox fits where the player is now.
+    pub fn can_stand(&self, terrain: &Terrain) -> bool {
+        let mut aabb = self.mv.aabb.clone();
+        aabb.rescale(self.standing_scale);
+        terrain.is_rect_free(self.mv.position + aabb.offset, aabb.half_size, TileFilter::Solid)
+    }
+
+    pub fn pressing_into_wall(&self) -> bool {
+        (self.input.left && !self.input.right && self.mv.pushes_left_wall) ||
+            (self.input.right && !self.input.left && self.mv.pushes_right_wall)
+    }
+
     pub fn stop(&mut self) {
         self.mv.accel.x = 0.0;
         self.mv.velocity.x = 0.0;
     }
 
     pub fn slow_down(&mut self, fast: bool) {
+        let walk_accel = self.profile.walk_accel;
         if fast {
-            if self.mv.velocity.x > Player::WALK_ACCEL / 2.0 {
-                self.mv.accel.x = -Player::WALK_ACCEL * 5.0;
-            } else if self.mv.velocity.x < -Player::WALK_ACCEL / 2.0 {
-                self.mv.accel.x = Player::WALK_ACCEL * 5.0;
+            if self.mv.velocity.x > walk_accel / 2.0 {
+                self.mv.accel.x = -walk_accel * self.profile.ground_friction;
+            } else if self.mv.velocity.x < -walk_accel / 2.0 {
+                self.mv.accel.x = walk_accel * self.profile.ground_friction;
             } else {
                 self.stop();
             }
         } else {
-            self.mv.accel.x = -self.mv.velocity.x / 3.0;
+            self.mv.accel.x = -self.mv.velocity.x * self.profile.air_drag;
+        }
+    }
+}
+
+impl Controlled for Player {
+    type Id = PlayerState;
+    type Env = Terrain;
+    type Snapshot = PlayerInput;
+
+    fn state(id: PlayerState) -> Box<State<Player>> {
+        match id {
+            PlayerState::Idle => Box::new(Idle),
+            PlayerState::Running => Box::new(Running),
+            PlayerState::Jumping => Box::new(Jumping),
+            PlayerState::Sliding => Box::new(Sliding::new()),
+            PlayerState::Attacking => Box::new(Attacking),
+            PlayerState::LedgeGrab => Box::new(LedgeGrab),
+            PlayerState::LedgeClimb => Box::new(LedgeClimb),
+            PlayerState::WallSlide => Box::new(WallSlide),
+            PlayerState::AirDash => Box::new(AirDash),
+            PlayerState::Hurt => Box::new(Hurt),
+            PlayerState::Ready => Box::new(Ready),
         }
     }
 
-    pub const GRAVITY: f64 = -3000.0;
-    pub const MAX_FALLING_SPEED: f64 = -4000.0;
-    pub const JUMP_SPEED: f64 = 1600.0;
-    pub const WALK_SPEED: f64 = 1100.0;
-    pub const WALK_ACCEL: f64 = 700.0;
-    pub const JUMP_FRAMES_THRESHOLD: isize = 4;
+    fn events_handled(&mut self) {
+        self.input.reset_actions();
+    }
+
+    fn snapshot(&self) -> PlayerInput {
+        self.input.clone()
+    }
+}
+
+/// Transitions of the movement region shared by several states. Whatever
+/// only one state cares about, or that depends on the state's own data,
+/// stays in its `handle_events`.
+pub fn rules() -> Vec<Rule<Player>> {
+    use self::PlayerState as S;
+
+    let grounded = [S::Idle, S::Running];
+
+    vec![
+        Rule::new(
+            "fall",
+            &grounded,
+            |p: &Player| !p.mv.on_ground,
+            Trans::Push(S::Jumping),
+        ).priority(100),
+        Rule::new(
+            "jump",
+            &grounded,
+            |p: &Player| p.input.jump || p.ja.has_buffered(),
+            Trans::Push(S::Jumping),
+        ).priority(90)
+            .action(|p: &mut Player| p.ja.jump(&mut p.mv, &p.profile)),
+        Rule::new(
+            "drop_through",
+            &grounded,
+            |p: &Player| p.input.down,
+            Trans::Push(S::Jumping),
+        ).priority(80)
+            .action(|p: &mut Player| if p.mv.on_platform {
+                p.mv.position.y -= p.mv.platform_threshold * 2.0;
+            }),
+        Rule::new(
+            "stop",
+            &[S::Running],
+            |p: &Player| !(p.input.left ^ p.input.right),
+            Trans::Pop,
+        ).priority(70),
+        Rule::new(
+            "run",
+            &[S::Idle],
+            |p: &Player| p.input.left ^ p.input.right,
+            Trans::Push(S::Running),
+        ).priority(70),
+        Rule::new(
+            "slide",
+            &grounded,
+            |p: &Player| p.input.slide,
+            Trans::Push(S::Sliding),
+        ).priority(60),
+        Rule::new(
+            "air_dash",
+            &[S::Jumping],
+            |p: &Player| p.input.slide && p.dash.can_start(),
+            Trans::Switch(S::AirDash),
+        ).priority(40)
+            .action(|p: &mut Player| p.dash.start(&p.profile)),
+        Rule::new(
+            "air_jump",
+            &[S::Jumping],
+            |p: &Player| p.input.jump,
+            Trans::None,
+        ).priority(30)
+            .action(Jumping::air_jump),
+        Rule::new(
+            "ledge_jump",
+            &[S::LedgeGrab],
+            |p: &Player| p.input.jump,
+            Trans::Switch(S::Jumping),
+        ).priority(10)
+            .action(|p: &mut Player| p.ja.jump(&mut p.mv, &p.profile)),
+        Rule::new(
+            "ledge_drop",
+            &[S::LedgeGrab],
+            |p: &Player| p.input.down,
+            Trans::Switch(S::Jumping),
+        ).action(LedgeGrab::let_go),
+        Rule::new(
+            "wall_jump",
+            &[S::WallSlide],
+            |p: &Player| p.input.jump,
+            Trans::Switch(S::Jumping),
+        ).priority(10)
+            .action(WallSlide::wall_jump),
+        Rule::new(
+            "wall_drop",
+            &[S::WallSlide],
+            |p: &Player| p.input.down,
+            Trans::Switch(S::Jumping),
+        ).action(WallSlide::let_go),
+    ]
+}
+
+/// Transitions of the action region.
+pub fn action_rules() -> Vec<Rule<Player>> {
+    vec![
+        Rule::new(
+            "attack",
+            &[PlayerState::Ready],
+            |p: &Player| p.input.attack,
+            Trans::Push(PlayerState::Attacking),
+        ),
+    ]
+}
+
+/// Attacks need the hands free and a body that isn't busy otherwise:
+/// sliding, hanging, clinging to a wall or getting hit both prevent them
+/// and cut a running one short.
+pub fn conflicts() -> Vec<Conflict<PlayerState>> {
+    use self::PlayerState as S;
+
+    let mut conflicts = vec![];
+    for &busy in [S::Sliding, S::LedgeGrab, S::LedgeClimb, S::WallSlide, S::Hurt].iter() {
+        conflicts.push(Conflict::Blocks(busy, S::Attacking));
+        conflicts.push(Conflict::Cancels(busy, S::Attacking));
+    }
+    conflicts
 }
 
-fn draw_animation_frame(
-    player: &Player,
-    ctx: &mut Context,
-    camera: &Camera,
-    ss: &Animated,
-    direction: &Direction,
-) -> GameResult<()> {
-    let d: f32 = match *direction {
-        Direction::Left => -player.data.scale,
-        Direction::Right => player.data.scale,
-    };
-
-    let dest = Point::new(player.mv.position.x as f32, player.mv.position.y as f32);
-
-    (&*ss.marked_tiles.image).draw_ex_camera(
-        camera,
-        ctx,
-        DrawParam {
-            src: ss.current_frame_rect(),
-            dest,
-            rotation: 0.0,
-            scale: Point::new(d, player.data.scale),
-            offset: Point::new(0.0, 0.0),
-            ..Default::default()
-        },
-    )?;
-
-    debug::Debug::draw_aabb(ctx, player, camera);
-
-    Ok(())
+/// Frame counts of the player's animations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrames {
+    pub idle: usize,
+    pub running: usize,
+    pub jumping: usize,
+    pub attacking: usize,
+    pub sliding: usize,
 }
-pub struct PlayerData {
-    scale: f32,
-    idle: Animated,
-    running: Animated,
-    jumping: Animated,
-    attacking: Animated,
-    sliding: Animated,
+
+/// Where the player's animations are at. Part of the gameplay state, an
+/// attack lasts as long as its animation.
+#[derive(Debug, Clone)]
+pub struct PlayerAnimations {
+    pub idle: Animated,
+    pub running: Animated,
+    pub jumping: Animated,
+    pub attacking: Animated,
+    pub sliding: Animated,
 }
 
-impl PlayerData {
-    pub fn new(ctx: &mut Context, scale: f64) -> GameResult<PlayerData> {
-        let idle = Loader::load_sprite_sheet(ctx, "/idle")?;
-        let attacking = Loader::load_sprite_sheet(ctx, "/attack")?;
-        let jumping = Loader::load_sprite_sheet(ctx, "/jump")?;
-        let running = Loader::load_sprite_sheet(ctx, "/run")?;
-        let sliding = Loader::load_sprite_sheet(ctx, "/slide")?;
-
-        Ok(PlayerData {
-            scale: scale as f32,
-            idle: Animated::new(idle),
-            jumping: Animated::new(jumping),
-            running: Animated::new(running),
-            attacking: Animated::new(attacking),
-            sliding: Animated::new(sliding),
-        })
+impl PlayerAnimations {
+    pub fn new(frames: &AnimationFrames) -> PlayerAnimations {
+        PlayerAnimations {
+            idle: Animated::new(frames.idle),
+            running: Animated::new(frames.running),
+            jumping: Animated::new(frames.jumping),
+            attacking: Animated::new(frames.attacking),
+            sliding: Animated::new(frames.sliding),
+        }
     }
 }
 
-#[derive(Debug)]
+/// Fields left out of a recording are false.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PlayerInput {
     pub up: bool,
     pub down: bool,
@@ -194,6 +421,8 @@ pub struct PlayerInput {
     pub right: bool,
     pub slide: bool,
     pub jump: bool,
+    /// Whether the jump button is still down, `jump` only marks the press.
+    pub jump_held: bool,
     pub attack: bool,
 }
 
@@ -206,6 +435,7 @@ impl Default for PlayerInput {
             right: false,
             slide: false,
             jump: false,
+            jump_held: false,
             attack: false,
         }
     }
@@ -216,6 +446,18 @@ impl PlayerInput {
         PlayerInput::default()
     }
 
+    /// Takes this frame's actions.
+    pub fn read(&mut self, input: &Input) {
+        self.up = input.is_held(Action::Up);
+        self.down = input.is_held(Action::Down);
+        self.left = input.is_held(Action::Left);
+        self.right = input.is_held(Action::Right);
+        self.slide = input.was_pressed(Action::Slide);
+        self.jump = input.was_pressed(Action::Jump);
+        self.jump_held = input.is_held(Action::Jump);
+        self.attack = input.was_pressed(Action::Attack);
+    }
+
     pub fn reset_actions(&mut self) {
         self.attack = false;
         self.slide = false;
@@ -225,66 +467,36 @@ impl PlayerInput {
 
 pub struct Idle;
 
-impl State for Idle {
+impl State<Player> for Idle {
     fn on_start(&mut self, player: &mut Player) {
-        player.data.idle.reset();
+        player.anim.idle.reset();
         player.dj.enable();
     }
     fn on_resume(&mut self, player: &mut Player) {
         self.on_start(player);
     }
-    /// Executed on every frame before updating, for use in reacting to events.
-    fn handle_events(&mut self, player: &mut Player) -> Trans {
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
         player.direct();
-
-        let pi = &mut player.input;
-        let mv = &mut player.mv;
-
-        let trans = if !mv.on_ground {
-            Trans::Push(Box::new(Jumping))
-        } else if pi.jump {
-            mv.velocity.y = Player::JUMP_SPEED;
-            Trans::Push(Box::new(Jumping))
-        } else if pi.down {
-            if mv.on_platform {
-                mv.position.y -= MovingObject::PLATFORM_THRESHOLD * 2.0;
-            };
-            Trans::Push(Box::new(Jumping))
-        } else if pi.left ^ pi.right {
-            Trans::Push(Box::new(Running))
-        } else if pi.slide {
-            Trans::Push(Box::new(Sliding))
-        } else if pi.attack {
-            Trans::Push(Box::new(Attacking))
-        } else {
-            Trans::None
-        };
-
-        pi.reset_actions();
-        trans
+        Trans::None
     }
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
         player.slow_down(true);
-        player.mv.update_physics(duration, terrain);
+        player.update_physics(duration, terrain);
         Trans::None
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        player.data.idle.roll_frames();
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.idle.roll_frames();
         Trans::None
     }
-
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(player, ctx, camera, &player.data.idle, &player.direction).unwrap();
-    }
 }
 
 pub struct Running;
 
-impl State for Running {
+impl State<Player> for Running {
     fn on_start(&mut self, player: &mut Player) {
-        player.data.running.reset();
+        player.anim.running.reset();
         player.dj.enable();
     }
     fn on_resume(&mut self, player: &mut Player) {
@@ -293,70 +505,50 @@ impl State for Running {
     fn on_pause(&mut self, _player: &mut Player) {}
     fn on_stop(&mut self, _player: &mut Player) {}
 
-    fn handle_events(&mut self, player: &mut Player) -> Trans {
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
         player.direct();
-        let pi = &mut player.input;
-        let mv = &mut player.mv;
-
-        if !(pi.left ^ pi.right) {
-            return Trans::Switch(Box::new(Idle));
-        };
-
-        let t = if !mv.on_ground {
-            Trans::Push(Box::new(Jumping))
-        } else if pi.jump {
-            mv.velocity.y = Player::JUMP_SPEED;
-            Trans::Push(Box::new(Jumping))
-        } else if pi.down {
-            if mv.on_platform {
-                mv.position.y -= MovingObject::PLATFORM_THRESHOLD * 2.0;
-            }
-            Trans::Push(Box::new(Jumping))
-        } else if pi.slide {
-            Trans::Push(Box::new(Sliding))
-        } else if pi.attack {
-            Trans::Push(Box::new(Attacking))
-        } else {
-            Trans::None
-        };
-
-        pi.reset_actions();
-        t
+        Trans::None
     }
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
 
         player.movement();
-        player.mv.update_physics(duration, terrain);
+        player.update_physics(duration, terrain);
         Trans::None
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        player.data.running.cycle_frames();
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.running.cycle_frames();
         Trans::None
     }
-
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(player, ctx, camera, &player.data.running, &player.direction).unwrap();
-    }
 }
 
 pub struct Jumping;
 
-impl State for Jumping {
-    fn on_start(&mut self, player: &mut Player) {
-        player.data.jumping.reset();
-
-        if !player.mv.on_ground && player.mv.was_on_ground {
-            player.mv.frames_from_jump_start = 0;
+impl Jumping {
+    /// Jump pressed in the air: a late jump off a ledge, a double jump or
+    /// a press remembered for landing.
+    fn air_jump(player: &mut Player) {
+        if player.ja.can_coyote_jump(&player.mv, &player.profile) {
+            player.ja.jump(&mut player.mv, &player.profile);
+        } else if player.dj.double_jump(&mut player.mv, &player.profile) {
+            player.ja.jumped();
+        } else {
+            player.ja.buffer(&player.profile);
         }
     }
+}
+
+impl State<Player> for Jumping {
+    fn on_start(&mut self, player: &mut Player) {
+        player.anim.jumping.reset();
+    }
 
     fn on_resume(&mut self, player: &mut Player) {
         self.on_start(player)
     }
 
-    fn handle_events(&mut self, player: &mut Player) -> Trans {
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
         player.direct();
 
         if player.mv.cannot_go_left_frames > 0 {
@@ -373,36 +565,27 @@ impl State for Jumping {
             player.movement();
         };
 
-        let t = if player.input.attack {
-            Trans::Switch(Box::new(Attacking))
-        } else if player.input.jump {
-            if player.mv.frames_from_jump_start <= Player::JUMP_FRAMES_THRESHOLD &&
-                player.mv.velocity.y <= 0.0 && !player.mv.at_ceiling
-            {
-                player.mv.velocity.y = Player::JUMP_SPEED;
-                Trans::None
-            } else {
-                player.dj.double_jump(&mut player.mv);
-                Trans::None
-            }
-        } else {
-            Trans::None
-        };
+        player
+            .ja
+            .cut(&mut player.mv, &player.profile, player.input.jump_held);
 
-        player.input.reset_actions();
-        t
+        Trans::None
     }
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
-        let y_vel = Player::GRAVITY * seconds(&duration) + player.mv.velocity.y;
-        player.mv.velocity.y = y_vel.max(Player::MAX_FALLING_SPEED);
-        player.mv.update_physics(duration, terrain);
-        let gl = player.lg.grab_ledge(&mut player.mv, &player.input, terrain);
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        let y_vel = player.profile.gravity * seconds(&duration) + player.mv.velocity.y;
+        player.mv.velocity.y = y_vel.max(player.profile.max_falling_speed);
+        player.update_physics(duration, terrain);
+        let gl = player
+            .lg
+            .grab_ledge(&mut player.mv, &player.input, &player.profile, terrain);
 
         if player.mv.on_ground {
             Trans::Pop
         } else if gl {
-            Trans::Switch(Box::new(LedgeGrab))
+            Trans::Switch(PlayerState::LedgeGrab)
+        } else if player.mv.velocity.y < 0.0 && player.pressing_into_wall() {
+            Trans::Switch(PlayerState::WallSlide)
         } else if !(player.input.left ^ player.input.right) {
             player.slow_down(false);
             Trans::None
@@ -411,167 +594,325 @@ impl State for Jumping {
         }
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        player.data.jumping.roll_frames();
-        if player.mv.frames_from_jump_start <= Player::JUMP_FRAMES_THRESHOLD {
-            if player.mv.at_ceiling || player.mv.velocity.y > 0.0 {
-                player.mv.frames_from_jump_start = Player::JUMP_FRAMES_THRESHOLD + 1;
-            }
-        }
-
-        player.mv.frames_from_jump_start += 1;
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.jumping.roll_frames();
         Trans::None
     }
+}
 
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(player, ctx, camera, &player.data.jumping, &player.direction).unwrap();
-    }
+pub struct Sliding {
+    headroom: bool,
 }
 
-pub struct Sliding;
+impl Sliding {
+    pub fn new() -> Sliding {
+        Sliding { headroom: true }
+    }
+}
 
-impl State for Sliding {
+impl State<Player> for Sliding {
     fn on_start(&mut self, player: &mut Player) {
-        player.data.sliding.reset();
+        player.anim.sliding.reset();
+        player.crouch();
+        player.stuck_crouching = false;
+
+        let speed = player.mv.velocity.x.abs().max(player.profile.slide_speed);
+        player.mv.velocity.x = player.facing() * speed;
+        player.mv.accel.x = 0.0;
     }
 
-    fn handle_events(&mut self, player: &mut Player) -> Trans {
-        let t = if player.input.jump {
-            Trans::Switch(Box::new(Jumping))
+    /// Interrupted under a low ceiling, e.g. by a hit, the player stays
+    /// crouched until there's room.
+    fn on_stop(&mut self, player: &mut Player) {
+        if self.headroom {
+            player.stand();
         } else {
-            Trans::None
-        };
-
-        player.input.reset_actions();
-        t
+            player.stuck_crouching = true;
+        }
     }
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
-        player.mv.update_physics(duration, terrain);
-        Trans::None
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        if player.input.jump && self.headroom {
+            player.ja.jump(&mut player.mv, &player.profile);
+            Trans::Switch(PlayerState::Jumping)
+        } else {
+            Trans::None
+        }
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        if player.data.sliding.is_over() {
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        let dt = seconds(duration);
+        let dir = player.facing();
+        let speed = (player.mv.velocity.x.abs() - player.profile.slide_friction * dt).max(0.0);
+        player.mv.velocity.x = dir * speed;
+
+        let y_vel = player.profile.gravity * dt + player.mv.velocity.y;
+        player.mv.velocity.y = y_vel.max(player.profile.max_falling_speed);
+
+        player.update_physics(duration, terrain);
+        self.headroom = player.can_stand(terrain);
+
+        if !self.headroom {
+            if speed <= player.profile.slide_stop_speed {
+                player.mv.velocity.x = dir * player.profile.slide_stop_speed;
+            }
+            Trans::None
+        } else if !player.mv.on_ground {
+            Trans::Switch(PlayerState::Jumping)
+        } else if speed <= player.profile.slide_stop_speed {
             Trans::Pop
         } else {
-            player.data.sliding.next_frame();
             Trans::None
         }
     }
 
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(player, ctx, camera, &player.data.sliding, &player.direction).unwrap();
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.sliding.next_frame();
+        Trans::None
     }
 }
 
+/// Nothing going on in the action region, the movement region draws.
+pub struct Ready;
+
+impl State<Player> for Ready {}
+
+/// Runs in the action region, so the movement state underneath keeps
+/// moving the body and only the drawing is taken over.
 pub struct Attacking;
 
 impl Attacking {
     fn can_cancel(&self, player: &Player) -> bool {
-        player.data.attacking.current_frame > 5
+        player.anim.attacking.current_frame > 5
     }
 }
 
-impl State for Attacking {
+impl State<Player> for Attacking {
     fn on_start(&mut self, player: &mut Player) {
-        player.data.attacking.reset();
+        player.anim.attacking.reset();
     }
 
-    fn handle_events(&mut self, player: &mut Player) -> Trans {
-        player.direct();
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        if self.can_cancel(player) && player.input.attack {
+            Trans::Switch(PlayerState::Attacking)
+        } else {
+            Trans::None
+        }
+    }
 
-        let t = if self.can_cancel(player) {
-            if player.input.jump {
-                Trans::Switch(Box::new(Jumping))
-            } else if player.input.slide {
-                Trans::Switch(Box::new(Sliding))
-            } else if player.input.attack {
-                Trans::Switch(Box::new(Attacking))
-            } else {
-                Trans::None
-            }
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        if player.anim.attacking.is_over() {
+            Trans::Pop
         } else {
+            player.anim.attacking.next_frame();
             Trans::None
+        }
+    }
+}
+
+pub struct LedgeGrab;
+
+impl State<Player> for LedgeGrab {
+    fn on_start(&mut self, player: &mut Player) {
+        player.anim.idle.reset();
+        player.dj.enable();
+    }
+    fn on_resume(&mut self, player: &mut Player) {
+        self.on_start(player);
+    }
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        player.update_physics(duration, terrain);
+
+        if player.input.up {
+            if let Some(target) = player.lg.climb_target(&player.mv, terrain) {
+                player.lg.start_climb(&player.mv, target);
+                return Trans::Switch(PlayerState::LedgeClimb);
+            }
+        }
+
+        if player.input.left ^ player.input.right {
+            let dir = if player.input.right { 1.0 } else { -1.0 };
+            let dx = dir * player.profile.ledge_shimmy_speed * seconds(duration);
+
+            if !player
+                .lg
+                .shimmy(&mut player.mv, dx, &player.profile, terrain) &&
+                dir != player.lg.side
+            {
+                LedgeGrab::let_go(player);
+                return Trans::Switch(PlayerState::Jumping);
+            }
+        }
+
+        Trans::None
+    }
+
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.idle.roll_frames();
+        Trans::None
+    }
+}
+
+impl LedgeGrab {
+    fn let_go(player: &mut Player) {
+        if player.lg.side < 0.0 {
+            player.mv.cannot_go_left_frames = 3;
+        } else {
+            player.mv.cannot_go_right_frames = 3;
         };
+    }
+}
+
+pub struct LedgeClimb;
 
-        player.input.reset_actions();
-        t
+impl State<Player> for LedgeClimb {
+    fn on_start(&mut self, player: &mut Player) {
+        player.anim.jumping.reset();
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        if player.data.attacking.is_over() {
+    fn update(&mut self, player: &mut Player, duration: &Duration, _terrain: &Terrain) -> Trans<PlayerState> {
+        if player.lg.climb(&mut player.mv, duration, &player.profile) {
             Trans::Pop
         } else {
-            player.data.attacking.next_frame();
             Trans::None
         }
     }
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
-        player.mv.update_physics(duration, terrain);
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.jumping.next_frame();
         Trans::None
     }
+}
+
+pub struct WallSlide;
+
+impl WallSlide {
+    fn wall_jump(player: &mut Player) {
+        let lockout = player.profile.wall_jump_lockout_frames;
+        let away = if player.mv.pushes_left_wall {
+            player.mv.cannot_go_left_frames = lockout;
+            player.direction = Direction::Right;
+            1.0
+        } else {
+            player.mv.cannot_go_right_frames = lockout;
+            player.direction = Direction::Left;
+            -1.0
+        };
+
+        player.mv.velocity = Vector2::new(
+            away * player.profile.wall_jump_x,
+            player.profile.wall_jump_y,
+        );
+        player.ja.jumped();
+    }
 
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(
-            player,
-            ctx,
-            &camera,
-            &player.data.attacking,
-            &player.direction,
-        ).unwrap();
+    fn let_go(player: &mut Player) {
+        if player.mv.pushes_left_wall {
+            player.mv.cannot_go_left_frames = player.profile.wall_jump_lockout_frames;
+        } else {
+            player.mv.cannot_go_right_frames = player.profile.wall_jump_lockout_frames;
+        }
     }
 }
 
-pub struct LedgeGrab;
-
-impl State for LedgeGrab {
+impl State<Player> for WallSlide {
     fn on_start(&mut self, player: &mut Player) {
-        player.data.idle.reset();
+        player.anim.idle.reset();
         player.dj.enable();
     }
-    fn on_resume(&mut self, player: &mut Player) {
-        self.on_start(player);
+
+    fn handle_events(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.direct();
+        Trans::None
     }
-    /// Executed on every frame before updating, for use in reacting to events.
-    fn handle_events(&mut self, _player: &mut Player) -> Trans {
+
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        let y_vel = player.profile.gravity * seconds(&duration) + player.mv.velocity.y;
+        player.mv.velocity.y = y_vel.max(-player.profile.wall_slide_speed);
+        player.movement();
+        player.update_physics(duration, terrain);
+        let gl = player
+            .lg
+            .grab_ledge(&mut player.mv, &player.input, &player.profile, terrain);
+
+        if player.mv.on_ground {
+            Trans::Pop
+        } else if gl {
+            Trans::Switch(PlayerState::LedgeGrab)
+        } else if !player.pressing_into_wall() {
+            Trans::Switch(PlayerState::Jumping)
+        } else {
+            Trans::None
+        }
+    }
+
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.idle.roll_frames();
         Trans::None
     }
+}
 
-    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans {
-        player.mv.update_physics(duration, terrain);
+pub struct AirDash;
 
-        let ledge_on_left = player.lg.ledge_tile.0 as f64 * terrain.tile_size <
-            player.mv.position.x;
-        let ledge_on_right = !ledge_on_left;
+impl State<Player> for AirDash {
+    fn on_start(&mut self, player: &mut Player) {
+        player.anim.sliding.reset();
+        player.mv.velocity = Vector2::new(player.facing() * player.profile.dash_speed, 0.0);
+        player.mv.accel = Vector2::new(0.0, 0.0);
+    }
 
-        let state = if player.input.down || (player.input.right && ledge_on_left) ||
-            (player.input.left && ledge_on_right)
-        {
-            if ledge_on_left {
-                player.mv.cannot_go_left_frames = 3;
-            } else {
-                player.mv.cannot_go_right_frames = 3;
-            };
-            Trans::Switch(Box::new(Jumping))
-        } else if player.input.jump {
-            player.mv.velocity.y = Player::JUMP_SPEED;
-            Trans::Switch(Box::new(Jumping))
+    fn on_stop(&mut self, player: &mut Player) {
+        let walk_speed = player.profile.walk_speed;
+        player.mv.velocity.x = player.mv.velocity.x.max(-walk_speed).min(walk_speed);
+    }
+
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        player.mv.velocity.y = 0.0;
+        player.update_physics(duration, terrain);
+
+        let dashing = player.dash.dashing(duration);
+
+        if player.mv.on_ground {
+            Trans::Pop
+        } else if !dashing || player.mv.pushes_left_wall || player.mv.pushes_right_wall {
+            Trans::Switch(PlayerState::Jumping)
         } else {
             Trans::None
-        };
-
-        player.input.reset_actions();
-        state
+        }
     }
 
-    fn fixed_update(&mut self, player: &mut Player) -> Trans {
-        player.data.idle.roll_frames();
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.sliding.next_frame();
         Trans::None
     }
+}
+
+/// Hitstun after getting hit, the player tumbles with the knockback and
+/// doesn't react to input until it's over.
+pub struct Hurt;
 
-    fn draw(&mut self, ctx: &mut Context, player: &Player, camera: &Camera) {
-        draw_animation_frame(player, ctx, camera, &player.data.idle, &player.direction).unwrap();
+impl State<Player> for Hurt {
+    fn on_start(&mut self, player: &mut Player) {
+        player.anim.jumping.reset();
+    }
+
+    fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) -> Trans<PlayerState> {
+        let y_vel = player.profile.gravity * seconds(&duration) + player.mv.velocity.y;
+        player.mv.velocity.y = y_vel.max(player.profile.max_falling_speed);
+        player.slow_down(player.mv.on_ground);
+        player.update_physics(duration, terrain);
+
+        if player.hurt.is_stunned() {
+            Trans::None
+        } else if player.mv.on_ground {
+            Trans::Pop
+        } else {
+            Trans::Switch(PlayerState::Jumping)
+        }
+    }
+
+    fn fixed_update(&mut self, player: &mut Player) -> Trans<PlayerState> {
+        player.anim.jumping.roll_frames();
+        Trans::None
     }
 }
@@ -0,0 +1,102 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{DrawParam, Point};
+
+use camera::*;
+use player::*;
+use sprite::{Loader, MarkedTiles};
+use sprite::animation::Animated;
+
+/// What players look like. Shared by all of them and only needed for
+/// drawing, the gameplay side gets by with `frames`.
+pub struct PlayerSprites {
+    idle: MarkedTiles,
+    running: MarkedTiles,
+    jumping: MarkedTiles,
+    attacking: MarkedTiles,
+    sliding: MarkedTiles,
+}
+
+impl PlayerSprites {
+    pub fn load(ctx: &mut Context) -> GameResult<PlayerSprites> {
+        Ok(PlayerSprites {
+            idle: Loader::load_sprite_sheet(ctx, "/idle")?,
+            running: Loader::load_sprite_sheet(ctx, "/run")?,
+            jumping: Loader::load_sprite_sheet(ctx, "/jump")?,
+            attacking: Loader::load_sprite_sheet(ctx, "/attack")?,
+            sliding: Loader::load_sprite_sheet(ctx, "/slide")?,
+        })
+    }
+
+    pub fn frames(&self) -> AnimationFrames {
+        AnimationFrames {
+            idle: self.idle.data.len(),
+            running: self.running.data.len(),
+            jumping: self.jumping.data.len(),
+            attacking: self.attacking.data.len(),
+            sliding: self.sliding.data.len(),
+        }
+    }
+
+    /// Draws the player the way `states` look, a layer each, see
+    /// `Regions::visible`.
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        camera: &Camera,
+        player: &Player,
+        states: &[PlayerState],
+    ) -> GameResult<()> {
+        for &state in states.iter() {
+            let (sheet, clock) = match state {
+                PlayerState::Idle | PlayerState::LedgeGrab | PlayerState::WallSlide => {
+                    (&self.idle, &player.anim.idle)
+                }
+                PlayerState::Running => (&self.running, &player.anim.running),
+                PlayerState::Jumping | PlayerState::LedgeClimb | PlayerState::Hurt => {
+                    (&self.jumping, &player.anim.jumping)
+                }
+                PlayerState::Sliding | PlayerState::AirDash => {
+                    (&self.sliding, &player.anim.sliding)
+                }
+                PlayerState::Attacking => (&self.attacking, &player.anim.attacking),
+                PlayerState::Ready => continue,
+            };
+
+            draw_animation_frame(ctx, camera, player, sheet, clock)?;
+        }
+        Ok(())
+    }
+}
+
+fn draw_animation_frame(
+    ctx: &mut Context,
+    camera: &Camera,
+    player: &Player,
+    sheet: &MarkedTiles,
+    clock: &Animated,
+) -> GameResult<()> {
+    if !player.hurt.visible() {
+        return Ok(());
+    }
+
+    let scale = Player::SCALE as f32;
+    let d: f32 = match player.direction {
+        Direction::Left => -scale,
+        Direction::Right => scale,
+    };
+
+    let dest = Point::new(player.mv.position.x as f32, player.mv.position.y as f32);
+
+    (&*sheet.image).draw_ex_camera(
+        camera,
+        ctx,
+        DrawParam {
+            src: sheet.frame_rect(clock.current_frame),
+            dest,
+            rotation: 0.0,
+            scale: Point::new(d, scale),
+            offset: Point::new(0.0, 0.0),
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,477 @@
+use ggez::{Context, GameError, GameResult};
+use serde_json;
+
+use std::io::Read;
+use std::time::Duration;
+
+use input::{AxisCurve, Bindings, Input};
+use level::{LevelData, LevelType, Terrain};
+use party::{DeviceKind, Party, Slot};
+use physics::{Contact, MovementProfile, TriggerEvent, TriggerKind};
+use physics::world::World;
+use player::{AnimationFrames, Player, PlayerInput};
+use Vector2;
+
+/// Ticks per second.
+pub const TICK_RATE: u32 = 60;
+/// Animations advance every this many ticks.
+const FIXED_UPDATE_EVERY: u64 = 2;
+
+pub fn tick_duration() -> Duration {
+    Duration::new(0, 1_000_000_000 / TICK_RATE)
+}
+
+/// Everything needed to play a session again: the level, the seed it was
+/// dressed with and what every player did on every tick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub level: String,
+    pub seed: u64,
+    pub tick_rate: u32,
+    /// Inputs of all players, one entry per tick.
+    pub ticks: Vec<Vec<PlayerInput>>,
+}
+
+impl Recording {
+    pub fn new(level: &str, seed: u64) -> Recording {
+        Recording {
+            level: level.to_owned(),
+            seed,
+            tick_rate: TICK_RATE,
+            ticks: vec![],
+        }
+    }
+
+    /// Saves to `<name>.json` in the user directory.
+    pub fn save(&self, ctx: &mut Context, name: &str) -> GameResult<()> {
+        let file = ctx.filesystem.create(format!("/{}.json", name))?;
+
+        serde_json::to_writer(file, self).map_err(|e| {
+            GameError::ResourceLoadError(format!("Can't save recording {}: {}", name, e))
+        })
+    }
+
+    pub fn from_reader<R: Read>(reader: R, name: &str) -> GameResult<Recording> {
+        let recording: Recording = serde_json::from_reader(reader).map_err(|e| {
+            GameError::ResourceLoadError(format!("Broken recording {}: {}", name, e))
+        })?;
+
+        if recording.tick_rate != TICK_RATE {
+            return Err(GameError::ResourceLoadError(format!(
+                "Recording {} runs at {} ticks per second, not {}",
+                name,
+                recording.tick_rate,
+                TICK_RATE
+            )));
+        }
+        Ok(recording)
+    }
+}
+
+/// Something that happened in the game the presentation may want to react
+/// to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Impact {
+    /// A player got hit by something at `from`.
+    Hurt { slot: usize, from: Vector2 },
+    /// A player landed, falling at `speed`.
+    Landed { slot: usize, speed: f64 },
+    /// A player went into or out of a trigger, for the log.
+    Triggered {
+        slot: usize,
+        name: String,
+        kind: TriggerKind,
+        contact: Contact,
+    },
+    /// A player reached the level exit named `name`.
+    LevelExit { slot: usize, name: String },
+    /// A player entered or left the camera zone named `name`.
+    CameraZone {
+        slot: usize,
+        name: String,
+        contact: Contact,
+    },
+}
+
+/// The gameplay side of the game: players and the things they run into,
+/// stepped at a fixed rate so the same inputs always play out the same way.
+/// Drawing and the camera stay with the caller.
+pub struct Simulation {
+    pub party: Party,
+    pub world: World,
+    tick: u64,
+    recording: Option<Recording>,
+    impacts: Vec<Impact>,
+}
+
+impl Simulation {
+    pub fn new(party: Party, world: World) -> Simulation {
+        Simulation {
+            party,
+            world,
+            tick: 0,
+            recording: None,
+            impacts: vec![],
+        }
+    }
+
+    /// Sets up the level of `recording` to play it back without a window:
+    /// the terrain, the triggers of `data`, which has to be that level's,
+    /// and a player per recorded input at the spawns. Players get no
+    /// devices, the recording drives them.
+    pub fn from_recording(
+        recording: &Recording,
+        data: &LevelData,
+        profile: &MovementProfile,
+        frames: &AnimationFrames,
+    ) -> GameResult<(Terrain, Simulation)> {
+        let level_type = LevelType::from_name(&recording.level).ok_or_else(|| {
+            GameError::ResourceLoadError(format!("Recording of unknown level {}", recording.level))
+        })?;
+        let terrain = Terrain::from_grid(&level_type.grid());
+
+        let mut world = World::new(&terrain);
+        for t in data.triggers.iter() {
+            world.add_trigger(t.to_trigger());
+        }
+
+        let spawns: Vec<Vector2> = data.spawns.iter().map(|s| s.position()).collect();
+        let players = recording.ticks.first().map_or(0, |inputs| inputs.len());
+        let mut slots = vec![];
+        for ix in 0..players {
+            let at = Party::spawn_point(&spawns, ix);
+            let (player, sm) = Player::new(at, profile.clone(), frames);
+            let input = Input::new(Bindings::new("replay", AxisCurve::default()));
+            slots.push(Slot::new(player, sm, input, DeviceKind::Any, world.add_body(), at));
+        }
+
+        Ok((terrain, Simulation::new(Party { slots }, world)))
+    }
+
+    /// Ticks run so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Starts remembering the inputs of every tick.
+    pub fn record(&mut self, level: &str, seed: u64) {
+        self.recording = Some(Recording::new(level, seed));
+    }
+
+    /// Stops recording, handing over what was recorded.
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    /// What happened since the last call.
+    pub fn take_impacts(&mut self) -> Vec<Impact> {
+        ::std::mem::replace(&mut self.impacts, vec![])
+    }
+
+    /// Runs a tick on what the players did since the last one.
+    pub fn step(&mut self, terrain: &Terrain) {
+        for slot in self.party.slots.iter_mut() {
+            slot.player.input.read(&slot.input);
+            slot.input.end_frame();
+        }
+        self.run(terrain);
+    }
+
+    /// Runs a tick on given inputs, one per player, ignoring the devices.
+    pub fn step_with(&mut self, inputs: &[PlayerInput], terrain: &Terrain) {
+        for (slot, input) in self.party.slots.iter_mut().zip(inputs.iter()) {
+            slot.player.input = input.clone();
+        }
+        self.run(terrain);
+    }
+
+    /// Plays back a whole recording, the simulation has to be set up for
+    /// it, see `from_recording`.
+    pub fn replay(&mut self, recording: &Recording, terrain: &Terrain) {
+        for inputs in recording.ticks.iter() {
+            self.step_with(inputs, terrain);
+        }
+    }
+
+    fn run(&mut self, terrain: &Terrain) {
+        if let Some(ref mut recording) = self.recording {
+            recording
+                .ticks
+                .push(self.party.slots.iter().map(|s| s.player.input.clone()).collect());
+        }
+
+        let dt = tick_duration();
+        let fixed = self.tick % FIXED_UPDATE_EVERY == 0;
+
+        for (ix, slot) in self.party.slots.iter_mut().enumerate() {
+            let was_on_ground = slot.player.mv.on_ground;
+            let falling = -slot.player.mv.velocity.y;
+
+            slot.sm.handle_events(&mut slot.player);
+            slot.sm.update(&mut slot.player, &dt, terrain);
+            if fixed {
+                slot.sm.fixed_update(&mut slot.player);
+            }
+
+            if !was_on_ground && slot.player.mv.on_ground {
+                self.impacts.push(Impact::Landed {
+                    slot: ix,
+                    speed: falling,
+                });
+            }
+        }
+
+        let events = {
+            let bodies: Vec<_> = self.party
+                .slots
+                .iter()
+                .map(|s| (s.body, &s.player.mv))
+                .collect();
+            self.world.update_triggers(&bodies)
+        };
+        for e in events {
+            self.on_trigger(e);
+        }
+
+        self.tick += 1;
+    }
+
+    fn on_trigger(&mut self, event: TriggerEvent) {
+        let (kind, center, name) = match self.world.trigger(event.trigger) {
+            Some(trigger) => (trigger.kind.clone(), trigger.center, trigger.name.clone()),
+            None => return,
+        };
+        let ix = match self.party.slot_of(event.body) {
+            Some(ix) => ix,
+            None => return,
+        };
+        if event.contact != Contact::Stay {
+            self.impacts.push(Impact::Triggered {
+                slot: ix,
+                name: name.clone(),
+                kind: kind.clone(),
+                contact: event.contact,
+            });
+        }
+        let slot = &mut self.party.slots[ix];
+
+        match (kind, event.contact) {
+            (TriggerKind::Checkpoint, Contact::Enter) => slot.checkpoint = center,
+            (TriggerKind::KillPlane, Contact::Enter) => slot.respawn(),
+            (TriggerKind::Hazard, Contact::Enter) | (TriggerKind::Hazard, Contact::Stay) => {
+                if slot.hurt(center) {
+                    self.impacts.push(Impact::Hurt {
+                        slot: ix,
+                        from: center,
+                    });
+                }
+            }
+            (TriggerKind::LevelExit, Contact::Enter) => {
+                self.impacts.push(Impact::LevelExit { slot: ix, name })
+            }
+            (TriggerKind::CameraZone, Contact::Enter) | (TriggerKind::CameraZone, Contact::Exit) => {
+                self.impacts.push(Impact::CameraZone {
+                    slot: ix,
+                    name,
+                    contact: event.contact,
+                })
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::{AxisCurve, Bindings, Input};
+    use party::{DeviceKind, Slot};
+    use physics::{MovementProfile, Trigger};
+    use player::*;
+    use Vector2;
+
+    // 128 wide tiles with tile (0, 0) centered on the origin: the floor's
+    // top is at y = 64 and the platform's at y = 448.
+    fn terrain() -> Terrain {
+        Terrain::from_ascii(
+            &[
+                "#......................................#",
+                "#......................................#",
+                "#......................................#",
+                "#......................................#",
+                "#......===============================.#",
+                "#......................................#",
+                "#......................................#",
+                "########################################",
+            ],
+            128.0,
+        )
+    }
+
+    fn profile() -> MovementProfile {
+        MovementProfile::from_reader(
+            include_str!("../resources/profiles/player.json").as_bytes(),
+            "player",
+        ).unwrap()
+    }
+
+    fn frames() -> AnimationFrames {
+        AnimationFrames {
+            idle: 8,
+            running: 8,
+            jumping: 8,
+            attacking: 8,
+            sliding: 8,
+        }
+    }
+
+    fn simulation(terrain: &Terrain) -> Simulation {
+        let mut world = World::new(terrain);
+        let at = Vector2::new(256.0, 200.0);
+        let (player, sm) = Player::new(at, profile(), &frames());
+        let input = Input::new(Bindings::new("test", AxisCurve::default()));
+        let slot = Slot::new(player, sm, input, DeviceKind::Any, world.add_body(), at);
+
+        Simulation::new(Party { slots: vec![slot] }, world)
+    }
+
+    /// Holds right for two seconds, then jumps while still holding it.
+    fn run_and_jump(sim: &mut Simulation, terrain: &Terrain) {
+        let mut right = PlayerInput::new();
+        right.right = true;
+        for _ in 0..2 * TICK_RATE {
+            sim.step_with(&[right.clone()], terrain);
+        }
+
+        let mut jump = right.clone();
+        jump.jump = true;
+        jump.jump_held = true;
+        sim.step_with(&[jump.clone()], terrain);
+
+        jump.jump = false;
+        for _ in 0..2 * TICK_RATE {
+            sim.step_with(&[jump.clone()], terrain);
+        }
+    }
+
+    fn feet(sim: &Simulation) -> f64 {
+        let mv = &sim.party.slots[0].player.mv;
+        mv.position.y + mv.aabb.offset.y - mv.aabb.half_size.y
+    }
+
+    #[test]
+    fn running_jump_lands_on_platform() {
+        let t = terrain();
+        let mut sim = simulation(&t);
+
+        run_and_jump(&mut sim, &t);
+
+        let mv = &sim.party.slots[0].player.mv;
+        assert!(mv.on_ground && mv.on_platform, "{:?}", mv.position);
+        assert!((feet(&sim) - 448.0).abs() < 1.0, "{:?}", mv.position);
+        assert_eq!(sim.tick(), 4 * TICK_RATE as u64 + 1);
+    }
+
+    #[test]
+    fn replay_plays_out_the_same() {
+        let t = terrain();
+        let mut sim = simulation(&t);
+        sim.record("test", 0);
+        run_and_jump(&mut sim, &t);
+
+        let saved = serde_json::to_string(&sim.stop_recording().unwrap()).unwrap();
+        let recording = Recording::from_reader(saved.as_bytes(), "test").unwrap();
+        let mut again = simulation(&t);
+        again.replay(&recording, &t);
+
+        assert_eq!(again.party.positions(), sim.party.positions());
+        assert_eq!(again.party.slots[0].sm.current(), sim.party.slots[0].sm.current());
+    }
+
+    #[test]
+    fn recorded_session_replays_in_the_graveyard() {
+        let recording = Recording::from_reader(
+            include_str!("../resources/recordings/graveyard.json").as_bytes(),
+            "graveyard",
+        ).unwrap();
+        let data = LevelData::from_reader(
+            include_str!("../resources/level/graveyard/level.json").as_bytes(),
+            "graveyard",
+        ).unwrap();
+        let (t, mut sim) =
+            Simulation::from_recording(&recording, &data, &profile(), &frames()).unwrap();
+        assert_eq!(sim.party.slots.len(), 2);
+
+        // Player 1 drops in, jumps on the spot and runs right under the
+        // blocks, player 2 runs left into the wall, then both stand still.
+        let mut landings = [0, 0];
+        for inputs in recording.ticks.iter() {
+            sim.step_with(inputs, &t);
+            for slot in sim.party.slots.iter_mut() {
+                let violations = slot.player.mv.diagnostics.drain();
+                assert!(violations.is_empty(), "tick {}: {:?}", sim.tick, violations);
+            }
+            for impact in sim.take_impacts() {
+                if let Impact::Landed { slot, .. } = impact {
+                    landings[slot] += 1;
+                }
+            }
+        }
+        assert_eq!(sim.tick(), recording.ticks.len() as u64);
+        assert_eq!(landings, [2, 1]);
+
+        // The floor's top is at y = 192 and the left wall's right side at
+        // x = 64.
+        for slot in sim.party.slots.iter() {
+            let mv = &slot.player.mv;
+            let feet = mv.position.y + mv.aabb.offset.y - mv.aabb.half_size.y;
+            assert!(mv.on_ground && (feet - 192.0).abs() < 1.0, "{:?}", mv.position);
+            assert_eq!(
+                slot.sm.current(),
+                vec![Some(PlayerState::Idle), Some(PlayerState::Ready)]
+            );
+        }
+        let one = &sim.party.slots[0].player.mv;
+        assert!(one.position.x > 500.0 && one.position.x < 1000.0, "{:?}", one.position);
+        let two = &sim.party.slots[1].player.mv;
+        assert!(two.pushes_left_wall);
+        assert!((two.position.x - two.aabb.half_size.x - 64.0).abs() < 1.0, "{:?}", two.position);
+    }
+
+    #[test]
+    fn walking_into_the_exit_ends_the_level() {
+        let t = terrain();
+        let mut sim = simulation(&t);
+        sim.world.add_trigger(Trigger::new(
+            "exit",
+            TriggerKind::LevelExit,
+            Vector2::new(640.0, 192.0),
+            Vector2::new(64.0, 128.0),
+        ));
+
+        let mut right = PlayerInput::new();
+        right.right = true;
+        let mut impacts = vec![];
+        for _ in 0..2 * TICK_RATE {
+            sim.step_with(&[right.clone()], &t);
+            impacts.extend(sim.take_impacts());
+        }
+
+        let exits: Vec<&Impact> = impacts
+            .iter()
+            .filter(|i| match **i {
+                Impact::LevelExit { .. } => true,
+                _ => false,
+            })
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                &Impact::LevelExit {
+                    slot: 0,
+                    name: "exit".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -1,19 +1,16 @@
-use super::MarkedTiles;
-use ggez::graphics::Rect;
-
+/// Which frame of an animation is showing. Only the clock, the frames
+/// themselves are in the sprite sheet, so gameplay can wait for animations
+/// to end without loading any images.
+#[derive(Debug, Clone)]
 pub struct Animated {
-    pub marked_tiles: MarkedTiles,
     pub current_frame: usize,
     pub length: usize,
     pub roll_forward: bool,
 }
 
 impl Animated {
-    pub fn new(mt: MarkedTiles) -> Animated {
-        let length = mt.data.len();
-
+    pub fn new(length: usize) -> Animated {
         Animated {
-            marked_tiles: mt,
             current_frame: 0,
             length,
             roll_forward: true,
@@ -72,12 +69,4 @@ impl Animated {
             false
         }
     }
-
-    pub fn current_frame_rect(&self) -> Rect {
-        Rect::from(
-            self.marked_tiles.data[self.current_frame]
-                .on_screen_frame
-                .clone(),
-        )
-    }
 }
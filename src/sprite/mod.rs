@@ -1,7 +1,7 @@
 
 use marker::*;
 use ggez::{GameResult, Context, GameError};
-use ggez::graphics::Image;
+use ggez::graphics::{Image, Rect};
 use serde_json;
 use std::rc::Rc;
 
@@ -32,3 +32,9 @@ pub struct MarkedTiles {
     pub data: Vec<SpriteData>,
     pub image: Rc<Image>,
 }
+
+impl MarkedTiles {
+    pub fn frame_rect(&self, frame: usize) -> Rect {
+        Rect::from(self.data[frame].on_screen_frame.clone())
+    }
+}
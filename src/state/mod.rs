@@ -1,180 +1,727 @@
-use ggez::Context;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter, Write};
+use std::time::Duration;
 
-use super::player::*;
-use super::camera::*;
-use super::level::Terrain;
+mod regions;
+pub use self::regions::*;
 
-use std::fmt::{Debug, Formatter};
-use std::result::Result;
-use std::fmt::Error;
-use std::time::Duration;
+/// Something driven by a `StateMachine`: the player, an enemy, a menu.
+pub trait Controlled: Sized + 'static {
+    /// Identifies the states, cheap to copy and compare.
+    type Id: Copy + PartialEq + Debug + 'static;
+    /// Whatever the states need to look at while updating, e.g. terrain.
+    type Env;
+    /// What gets recorded in the history next to every transition, usually
+    /// the input that caused it.
+    type Snapshot: Clone + Debug;
+
+    /// Builds the state for an id.
+    fn state(id: Self::Id) -> Box<State<Self>>;
+
+    /// Called once per frame after the current state and the transition
+    /// table had their look at the events, e.g. to clear one-shot input.
+    fn events_handled(&mut self) {}
+
+    fn snapshot(&self) -> Self::Snapshot;
+}
 
-#[derive(Debug)]
-pub enum Trans {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trans<Id> {
     None,
     Pop,
-    Push(Box<State>),
-    Switch(Box<State>),
+    Push(Id),
+    Switch(Id),
     Quit,
 }
 
-impl Debug for State {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        f.write_str("state!")?;
-        Ok(())
+impl<Id: Copy> Trans<Id> {
+    /// The state this transition starts, if any.
+    pub fn target(&self) -> Option<Id> {
+        match *self {
+            Trans::Push(id) | Trans::Switch(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+fn unless_blocked<Id: Copy + PartialEq>(trans: Trans<Id>, blocked: &[Id]) -> Trans<Id> {
+    match trans.target() {
+        Some(id) if blocked.contains(&id) => Trans::None,
+        _ => trans,
     }
 }
 
-pub trait State {
-    fn on_start(&mut self, _player: &mut Player) {}
-    fn on_stop(&mut self, _player: &mut Player) {}
-    fn on_pause(&mut self, _player: &mut Player) {}
-    fn on_resume(&mut self, _player: &mut Player) {}
+pub trait State<C: Controlled> {
+    fn on_start(&mut self, _c: &mut C) {}
+    fn on_stop(&mut self, _c: &mut C) {}
+    fn on_pause(&mut self, _c: &mut C) {}
+    fn on_resume(&mut self, _c: &mut C) {}
 
     /// Executed on every frame before updating, for use in reacting to events.
-    fn handle_events(&mut self, _player: &mut Player) -> Trans {
+    /// Returning `Trans::None` lets the transition table decide.
+    fn handle_events(&mut self, _c: &mut C) -> Trans<C::Id> {
         Trans::None
     }
 
     /// Executed repeatedly at stable, predictable intervals (1/60th of a second
     /// by default).
-    fn fixed_update(&mut self, _player: &mut Player) -> Trans {
+    fn fixed_update(&mut self, _c: &mut C) -> Trans<C::Id> {
         Trans::None
     }
 
     /// Executed on every frame immediately, as fast as the engine will allow.
-    fn update(&mut self, _player: &mut Player, _duration: &Duration, _terrain: &Terrain) -> Trans {
+    fn update(&mut self, _c: &mut C, _duration: &Duration, _env: &C::Env) -> Trans<C::Id> {
         Trans::None
     }
+}
 
-    fn draw(&mut self, _ctx: &mut Context, _player: &Player, _camera: &Camera) {}
+/// An entry of the transition table: while the current state is one of
+/// `from` and `condition` holds, run `action` and take `trans`. Rules with a
+/// higher priority are checked first.
+pub struct Rule<C: Controlled> {
+    pub name: &'static str,
+    pub from: Vec<C::Id>,
+    pub priority: i32,
+    pub condition: fn(&C) -> bool,
+    pub action: Option<fn(&mut C)>,
+    pub trans: Trans<C::Id>,
 }
 
-#[derive(Debug)]
-pub struct StateMachine {
+impl<C: Controlled> Rule<C> {
+    pub fn new(
+        name: &'static str,
+        from: &[C::Id],
+        condition: fn(&C) -> bool,
+        trans: Trans<C::Id>,
+    ) -> Rule<C> {
+        Rule {
+            name,
+            from: from.to_vec(),
+            priority: 0,
+            condition,
+            action: None,
+            trans,
+        }
+    }
+
+    pub fn priority(mut self, priority: i32) -> Rule<C> {
+        self.priority = priority;
+        self
+    }
+
+    pub fn action(mut self, action: fn(&mut C)) -> Rule<C> {
+        self.action = Some(action);
+        self
+    }
+
+    pub fn applies(&self, current: C::Id, c: &C) -> bool {
+        self.from.contains(&current) && (self.condition)(c)
+    }
+}
+
+/// What asked for a transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cause {
+    Events,
+    Rule(&'static str),
+    FixedUpdate,
+    Update,
+    Interrupt,
+    /// Ended by a state entered in another region.
+    Conflict,
+}
+
+/// A transition taken by the machine.
+#[derive(Debug, Clone)]
+pub struct Record<Id, S> {
+    /// Seconds of updates since the machine started.
+    pub time: f64,
+    pub cause: Cause,
+    pub from: Option<Id>,
+    pub trans: Trans<Id>,
+    /// The stack after the transition, bottom first.
+    pub stack: Vec<Id>,
+    pub snapshot: S,
+}
+
+/// The stack got deeper than the machine allows, usually a state pushed
+/// where it should have switched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthWarning<Id> {
+    pub time: f64,
+    pub stack: Vec<Id>,
+}
+
+impl<C: Controlled> Debug for Rule<C> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {:?} -> {:?}",
+            self.name,
+            self.priority,
+            self.from,
+            self.trans
+        )
+    }
+}
+
+pub struct StateMachine<C: Controlled> {
     running: bool,
-    state_stack: Vec<Box<State>>,
+    state_stack: Vec<(C::Id, Box<State<C>>)>,
+    rules: Vec<Rule<C>>,
+    clock: f64,
+    history: VecDeque<Record<C::Id, C::Snapshot>>,
+    history_len: usize,
+    max_depth: usize,
+    depth_warnings: VecDeque<DepthWarning<C::Id>>,
+    dropped_warnings: usize,
 }
 
-impl StateMachine {
-    pub fn new<T>(initial_state: T) -> StateMachine
-    where
-        T: State + 'static,
-    {
+impl<C: Controlled> StateMachine<C> {
+    const HISTORY_LEN: usize = 32;
+    const MAX_DEPTH: usize = 4;
+    /// Warnings kept until someone takes them.
+    const WARNINGS_LEN: usize = 16;
+
+    pub fn new(initial: C::Id) -> StateMachine<C> {
         StateMachine {
             running: false,
-            state_stack: vec![Box::new(initial_state)],
+            state_stack: vec![(initial, C::state(initial))],
+            rules: vec![],
+            clock: 0.0,
+            history: VecDeque::new(),
+            history_len: StateMachine::<C>::HISTORY_LEN,
+            max_depth: StateMachine::<C>::MAX_DEPTH,
+            depth_warnings: VecDeque::new(),
+            dropped_warnings: 0,
         }
     }
 
-    pub fn draw(&mut self, ctx: &mut Context, camera: &Camera, player: &Player) {
-        if let Some(state) = self.state_stack.last_mut() {
-            state.draw(ctx, player, camera);
+    /// How many transitions to remember.
+    pub fn with_history(mut self, len: usize) -> StateMachine<C> {
+        self.history_len = len;
+        self
+    }
+
+    /// Deepest the stack may get without a warning.
+    pub fn with_max_depth(mut self, depth: usize) -> StateMachine<C> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Replaces the transition table.
+    pub fn with_rules(mut self, mut rules: Vec<Rule<C>>) -> StateMachine<C> {
+        rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+        self.rules = rules;
+        self
+    }
+
+    pub fn rules(&self) -> &[Rule<C>] {
+        &self.rules
+    }
+
+    pub fn current(&self) -> Option<C::Id> {
+        self.state_stack.last().map(|&(id, _)| id)
+    }
+
+    /// Ids of the stacked states, bottom first.
+    pub fn stack(&self) -> Vec<C::Id> {
+        self.state_stack.iter().map(|&(id, _)| id).collect()
+    }
+
+    /// Remembered transitions, oldest first.
+    pub fn history(&self) -> &VecDeque<Record<C::Id, C::Snapshot>> {
+        &self.history
+    }
+
+    pub fn take_depth_warnings(&mut self) -> Vec<DepthWarning<C::Id>> {
+        self.depth_warnings.drain(..).collect()
+    }
+
+    /// Number of warnings thrown away because nobody took them in time.
+    pub fn dropped_depth_warnings(&self) -> usize {
+        self.dropped_warnings
+    }
+
+    /// The stack and the history in a readable form, for overlays, logs and
+    /// failing tests.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "stack: {:?}", self.stack()).unwrap();
+        for r in self.history.iter() {
+            writeln!(
+                out,
+                "{:9.3} {:?} {:?} {:?} -> {:?} {:?}",
+                r.time,
+                r.cause,
+                r.from,
+                r.trans,
+                r.stack,
+                r.snapshot
+            ).unwrap();
         }
+        out
+    }
+
+    /// Number of stacked states.
+    pub fn depth(&self) -> usize {
+        self.state_stack.len()
     }
 
     pub fn is_running(&self) -> bool {
         self.running
     }
 
-    pub fn start(&mut self, player: &mut Player) {
+    pub fn start(&mut self, c: &mut C) {
         if !self.running {
-            let state = self.state_stack.last_mut().unwrap();
-            state.on_start(player);
+            let state = &mut self.state_stack.last_mut().unwrap().1;
+            state.on_start(c);
             self.running = true;
         }
     }
 
-    pub fn handle_events(&mut self, player: &mut Player) {
+    pub fn handle_events(&mut self, c: &mut C) {
         if self.running {
-            let trans = match self.state_stack.last_mut() {
-                Some(state) => state.handle_events(player),
-                None => Trans::None,
-            };
-
-            self.transition(trans, player);
+            let snapshot = c.snapshot();
+            let (trans, cause) = self.poll_events(c, &[]);
+            c.events_handled();
+            self.transition(trans, c, cause, Some(snapshot));
         }
     }
 
-    pub fn fixed_update(&mut self, player: &mut Player) {
+    pub fn fixed_update(&mut self, c: &mut C) {
+        let trans = self.poll_fixed_update(c, &[]);
+        self.transition(trans, c, Cause::FixedUpdate, None);
+    }
+
+    pub fn update(&mut self, c: &mut C, duration: &Duration, env: &C::Env) {
+        let trans = self.poll_update(c, duration, env, &[]);
+        self.transition(trans, c, Cause::Update, None);
+    }
+
+    /// Drops every state above the base one and pushes `id`, for things
+    /// that happen whatever the current state is, like getting hit.
+    pub fn interrupt(&mut self, id: C::Id, c: &mut C) {
         if self.running {
-            let trans = match self.state_stack.last_mut() {
-                Some(state) => state.fixed_update(player),
-                None => Trans::None,
-            };
+            let from = self.current();
 
-            self.transition(trans, player);
+            while self.state_stack.len() > 1 {
+                if let Some((_, mut state)) = self.state_stack.pop() {
+                    state.on_stop(c);
+                }
+            }
+
+            self.push(id, c);
+            self.record(Cause::Interrupt, from, Trans::Push(id), c.snapshot());
         }
     }
 
-    pub fn update(&mut self, player: &mut Player, duration: &Duration, terrain: &Terrain) {
-        if self.running {
-            let trans = match self.state_stack.last_mut() {
-                Some(state) => state.update(player, duration, terrain),
-                None => Trans::None,
-            };
+    /// Pops every state above the base one and resumes it.
+    pub fn unwind(&mut self, c: &mut C, cause: Cause) {
+        if self.running && self.state_stack.len() > 1 {
+            let from = self.current();
 
-            self.transition(trans, player);
+            while self.state_stack.len() > 1 {
+                if let Some((_, mut state)) = self.state_stack.pop() {
+                    state.on_stop(c);
+                }
+            }
+            if let Some(&mut (_, ref mut state)) = self.state_stack.last_mut() {
+                state.on_resume(c);
+            }
+
+            self.record(cause, from, Trans::Pop, c.snapshot());
         }
     }
 
-    fn transition(&mut self, request: Trans, player: &mut Player) {
-        if self.running {
+    /// Asks the current state and then the transition table what to do
+    /// about the events, skipping anything that would enter a `blocked`
+    /// state. The action of a fired rule runs right away, the transition is
+    /// left to the caller.
+    fn poll_events(&mut self, c: &mut C, blocked: &[C::Id]) -> (Trans<C::Id>, Cause) {
+        if !self.running {
+            return (Trans::None, Cause::Events);
+        }
+
+        let (current, trans) = match self.state_stack.last_mut() {
+            Some(&mut (id, ref mut state)) => (Some(id), state.handle_events(c)),
+            None => (None, Trans::None),
+        };
+
+        match (unless_blocked(trans, blocked), current) {
+            (Trans::None, Some(id)) => self.fire_rules(id, c, blocked),
+            (trans, _) => (trans, Cause::Events),
+        }
+    }
+
+    fn poll_fixed_update(&mut self, c: &mut C, blocked: &[C::Id]) -> Trans<C::Id> {
+        if !self.running {
+            return Trans::None;
+        }
+
+        let trans = match self.state_stack.last_mut() {
+            Some(&mut (_, ref mut state)) => state.fixed_update(c),
+            None => Trans::None,
+        };
+        unless_blocked(trans, blocked)
+    }
+
+    fn poll_update(
+        &mut self,
+        c: &mut C,
+        duration: &Duration,
+        env: &C::Env,
+        blocked: &[C::Id],
+    ) -> Trans<C::Id> {
+        if !self.running {
+            return Trans::None;
+        }
+
+        self.clock += duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+        let trans = match self.state_stack.last_mut() {
+            Some(&mut (_, ref mut state)) => state.update(c, duration, env),
+            None => Trans::None,
+        };
+        unless_blocked(trans, blocked)
+    }
+
+    fn fire_rules(&self, current: C::Id, c: &mut C, blocked: &[C::Id]) -> (Trans<C::Id>, Cause) {
+        let fired = {
+            let c: &C = c;
+            self.rules.iter().find(|r| {
+                !r.trans.target().map_or(false, |id| blocked.contains(&id)) &&
+                    r.applies(current, c)
+            })
+        };
+
+        match fired {
+            Some(rule) => {
+                if let Some(action) = rule.action {
+                    action(c);
+                }
+                (rule.trans, Cause::Rule(rule.name))
+            }
+            None => (Trans::None, Cause::Events),
+        }
+    }
+
+    fn transition(
+        &mut self,
+        request: Trans<C::Id>,
+        c: &mut C,
+        cause: Cause,
+        snapshot: Option<C::Snapshot>,
+    ) {
+        if self.running && request != Trans::None {
+            let from = self.current();
+
             match request {
                 Trans::None => (),
-                Trans::Pop => self.pop(player),
-                Trans::Push(state) => self.push(state, player),
-                Trans::Switch(state) => self.switch(state, player),
-                Trans::Quit => self.stop(player),
+                Trans::Pop => self.pop(c),
+                Trans::Push(id) => self.push(id, c),
+                Trans::Switch(id) => self.switch(id, c),
+                Trans::Quit => self.stop(c),
             }
+
+            let snapshot = snapshot.unwrap_or_else(|| c.snapshot());
+            self.record(cause, from, request, snapshot);
         }
     }
 
-    fn switch(&mut self, state: Box<State>, player: &mut Player) {
+    fn record(&mut self, cause: Cause, from: Option<C::Id>, trans: Trans<C::Id>, snapshot: C::Snapshot) {
+        let stack = self.stack();
+
+        if stack.len() > self.max_depth {
+            if self.depth_warnings.len() >= StateMachine::<C>::WARNINGS_LEN {
+                self.depth_warnings.pop_front();
+                self.dropped_warnings += 1;
+            }
+            self.depth_warnings.push_back(DepthWarning {
+                time: self.clock,
+                stack: stack.clone(),
+            });
+        }
+
+        if self.history_len == 0 {
+            return;
+        }
+        if self.history.len() >= self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(Record {
+            time: self.clock,
+            cause,
+            from,
+            trans,
+            stack,
+            snapshot,
+        });
+    }
+
+    fn switch(&mut self, id: C::Id, c: &mut C) {
         if self.running {
-            if let Some(mut state) = self.state_stack.pop() {
-                state.on_stop(player)
+            if let Some((_, mut state)) = self.state_stack.pop() {
+                state.on_stop(c)
             }
 
-            self.state_stack.push(state);
-            let state = self.state_stack.last_mut().unwrap();
-            state.on_start(player);
+            self.state_stack.push((id, C::state(id)));
+            let state = &mut self.state_stack.last_mut().unwrap().1;
+            state.on_start(c);
         }
     }
 
-    fn push(&mut self, state: Box<State>, player: &mut Player) {
+    fn push(&mut self, id: C::Id, c: &mut C) {
         if self.running {
-            if let Some(state) = self.state_stack.last_mut() {
-                state.on_pause(player);
+            if let Some(&mut (_, ref mut state)) = self.state_stack.last_mut() {
+                state.on_pause(c);
             }
 
-            self.state_stack.push(state);
-            let state = self.state_stack.last_mut().unwrap();
-            state.on_start(player);
+            self.state_stack.push((id, C::state(id)));
+            let state = &mut self.state_stack.last_mut().unwrap().1;
+            state.on_start(c);
         }
     }
 
-    fn pop(&mut self, player: &mut Player) {
+    fn pop(&mut self, c: &mut C) {
         if self.running {
-            if let Some(mut state) = self.state_stack.pop() {
-                state.on_stop(player);
+            if let Some((_, mut state)) = self.state_stack.pop() {
+                state.on_stop(c);
             }
 
-            if let Some(state) = self.state_stack.last_mut() {
-                state.on_resume(player);
+            if let Some(&mut (_, ref mut state)) = self.state_stack.last_mut() {
+                state.on_resume(c);
             } else {
                 self.running = false;
             }
         }
     }
 
-    fn stop(&mut self, player: &mut Player) {
+    fn stop(&mut self, c: &mut C) {
         if self.running {
-            while let Some(mut state) = self.state_stack.pop() {
-                state.on_stop(player);
+            while let Some((_, mut state)) = self.state_stack.pop() {
+                state.on_stop(c);
             }
 
             self.running = false;
         }
     }
 }
+
+impl<C: Controlled> Debug for StateMachine<C> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("StateMachine")
+            .field("running", &self.running)
+            .field("stack", &self.stack())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum DoorState {
+        Closed,
+        Open,
+        Locked,
+    }
+
+    #[derive(Default)]
+    struct Door {
+        push: bool,
+        key: bool,
+        handled: usize,
+        creaks: usize,
+    }
+
+    struct Still;
+
+    impl State<Door> for Still {}
+
+    impl Controlled for Door {
+        type Id = DoorState;
+        type Env = ();
+        type Snapshot = bool;
+
+        fn state(_id: DoorState) -> Box<State<Door>> {
+            Box::new(Still)
+        }
+
+        fn events_handled(&mut self) {
+            self.push = false;
+            self.handled += 1;
+        }
+
+        fn snapshot(&self) -> bool {
+            self.push
+        }
+    }
+
+    fn machine() -> StateMachine<Door> {
+        StateMachine::<Door>::new(DoorState::Closed).with_rules(vec![
+            Rule::new(
+                "open",
+                &[DoorState::Closed],
+                |d: &Door| d.push,
+                Trans::Push(DoorState::Open),
+            ).action(|d: &mut Door| d.creaks += 1),
+            Rule::new(
+                "lock",
+                &[DoorState::Closed, DoorState::Open],
+                |d: &Door| d.key,
+                Trans::Switch(DoorState::Locked),
+            ).priority(10),
+            Rule::new("close", &[DoorState::Open], |d: &Door| d.push, Trans::Pop),
+        ])
+    }
+
+    #[test]
+    fn rules_fire_from_their_states_only() {
+        let mut door = Door::default();
+        let mut sm = machine();
+        sm.start(&mut door);
+
+        door.push = true;
+        sm.handle_events(&mut door);
+        assert_eq!(sm.stack(), vec![DoorState::Closed, DoorState::Open]);
+        assert_eq!(door.creaks, 1);
+        assert!(!door.push);
+
+        door.push = true;
+        sm.handle_events(&mut door);
+        assert_eq!(sm.current(), Some(DoorState::Closed));
+        assert_eq!(door.creaks, 1);
+        assert_eq!(door.handled, 2);
+    }
+
+    #[test]
+    fn higher_priority_wins() {
+        let mut door = Door::default();
+        let mut sm = machine();
+        sm.start(&mut door);
+
+        door.push = true;
+        door.key = true;
+        sm.handle_events(&mut door);
+        assert_eq!(sm.stack(), vec![DoorState::Locked]);
+        assert_eq!(sm.rules()[0].name, "lock");
+    }
+
+    #[test]
+    fn interrupt_keeps_only_the_base() {
+        let mut door = Door::default();
+        let mut sm = machine();
+        sm.start(&mut door);
+
+        door.push = true;
+        sm.handle_events(&mut door);
+        sm.interrupt(DoorState::Locked, &mut door);
+        assert_eq!(sm.stack(), vec![DoorState::Closed, DoorState::Locked]);
+    }
+
+    #[test]
+    fn history_records_cause_and_snapshot() {
+        let mut door = Door::default();
+        let mut sm = machine();
+        sm.start(&mut door);
+
+        door.push = true;
+        sm.handle_events(&mut door);
+        sm.update(&mut door, &Duration::from_millis(500), &());
+        door.push = true;
+        sm.handle_events(&mut door);
+
+        let history: Vec<_> = sm.history()
+            .iter()
+            .map(|r| (r.time, r.cause, r.trans, r.snapshot))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (0.0, Cause::Rule("open"), Trans::Push(DoorState::Open), true),
+                (0.5, Cause::Rule("close"), Trans::Pop, true),
+            ],
+            "{}",
+            sm.dump()
+        );
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut door = Door::default();
+        let mut sm = machine().with_history(3);
+        sm.start(&mut door);
+
+        for _ in 0..5 {
+            door.push = true;
+            sm.handle_events(&mut door);
+        }
+
+        assert_eq!(sm.history().len(), 3);
+        assert_eq!(sm.history()[2].stack, vec![DoorState::Closed, DoorState::Open]);
+    }
+
+    #[test]
+    fn deep_stack_warns() {
+        let mut door = Door::default();
+        let mut sm = machine().with_max_depth(2);
+        sm.start(&mut door);
+
+        door.push = true;
+        sm.handle_events(&mut door);
+        assert!(sm.take_depth_warnings().is_empty());
+
+        sm.interrupt(DoorState::Open, &mut door);
+        door.key = true;
+        sm.handle_events(&mut door);
+        assert!(sm.take_depth_warnings().is_empty());
+
+        let mut deep = StateMachine::<Door>::new(DoorState::Closed)
+            .with_max_depth(2)
+            .with_rules(vec![
+                Rule::new(
+                    "again",
+                    &[DoorState::Closed, DoorState::Open],
+                    |d: &Door| d.push,
+                    Trans::Push(DoorState::Open),
+                ),
+            ]);
+        deep.start(&mut door);
+        for _ in 0..3 {
+            door.push = true;
+            deep.handle_events(&mut door);
+        }
+
+        let warnings = deep.take_depth_warnings();
+        assert_eq!(warnings.len(), 2, "{}", deep.dump());
+        assert_eq!(warnings[0].stack.len(), 3);
+        assert!(deep.take_depth_warnings().is_empty());
+    }
+
+    #[test]
+    fn depth_warnings_are_bounded() {
+        let mut door = Door::default();
+        let mut sm = StateMachine::<Door>::new(DoorState::Closed)
+            .with_max_depth(2)
+            .with_rules(vec![
+                Rule::new(
+                    "again",
+                    &[DoorState::Closed, DoorState::Open],
+                    |d: &Door| d.push,
+                    Trans::Push(DoorState::Open),
+                ),
+            ]);
+        sm.start(&mut door);
+        for _ in 0..20 {
+            door.push = true;
+            sm.handle_events(&mut door);
+        }
+
+        let warnings = sm.take_depth_warnings();
+        assert_eq!(warnings.len(), 16);
+        assert_eq!(sm.dropped_depth_warnings(), 3);
+        assert_eq!(warnings[0].stack.len(), 6);
+    }
+}
@@ -0,0 +1,296 @@
+use super::*;
+
+/// How states in different regions get along.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conflict<Id> {
+    /// While the first state is stacked in one region the second can't be
+    /// entered in any other.
+    Blocks(Id, Id),
+    /// Entering the first state ends the second wherever it's stacked,
+    /// unwinding that region to its base state.
+    Cancels(Id, Id),
+}
+
+/// Several state machines driving the same thing side by side, e.g. the legs
+/// and the arms of the player. Every region has its own stack, rules and
+/// history; `Conflict`s settle what may run together.
+///
+/// Regions listed later show on top: every region is drawn as a layer over
+/// the ones before it, so an attack animation goes over the running one
+/// while it lasts.
+pub struct Regions<C: Controlled> {
+    regions: Vec<StateMachine<C>>,
+    conflicts: Vec<Conflict<C::Id>>,
+}
+
+impl<C: Controlled> Regions<C> {
+    pub fn new(regions: Vec<StateMachine<C>>) -> Regions<C> {
+        Regions {
+            regions,
+            conflicts: vec![],
+        }
+    }
+
+    pub fn with_conflicts(mut self, conflicts: Vec<Conflict<C::Id>>) -> Regions<C> {
+        self.conflicts = conflicts;
+        self
+    }
+
+    pub fn region(&self, ix: usize) -> &StateMachine<C> {
+        &self.regions[ix]
+    }
+
+    pub fn regions(&self) -> &[StateMachine<C>] {
+        &self.regions
+    }
+
+    pub fn regions_mut(&mut self) -> &mut [StateMachine<C>] {
+        &mut self.regions
+    }
+
+    pub fn conflicts(&self) -> &[Conflict<C::Id>] {
+        &self.conflicts
+    }
+
+    /// Current state of every region.
+    pub fn current(&self) -> Vec<Option<C::Id>> {
+        self.regions.iter().map(|r| r.current()).collect()
+    }
+
+    /// Whether `id` is stacked in any region.
+    pub fn is_active(&self, id: C::Id) -> bool {
+        self.regions.iter().any(|r| r.stack().contains(&id))
+    }
+
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for (ix, region) in self.regions.iter().enumerate() {
+            writeln!(out, "region {}", ix).unwrap();
+            out.push_str(&region.dump());
+        }
+        out
+    }
+
+    /// The states to draw, a layer per region, the first region at the
+    /// bottom.
+    pub fn visible(&self) -> Vec<C::Id> {
+        self.regions.iter().filter_map(|r| r.current()).collect()
+    }
+
+    pub fn start(&mut self, c: &mut C) {
+        for region in self.regions.iter_mut() {
+            region.start(c);
+        }
+    }
+
+    /// Every region looks at the same events before they're cleared.
+    pub fn handle_events(&mut self, c: &mut C) {
+        let snapshot = c.snapshot();
+
+        // Each region's transition goes in before the next one polls, so
+        // rule actions of states an earlier region just blocked never run.
+        for ix in 0..self.regions.len() {
+            let blocked = self.blocked(ix);
+            let (trans, cause) = self.regions[ix].poll_events(c, &blocked);
+            self.apply(ix, trans, c, cause, Some(snapshot.clone()));
+        }
+
+        c.events_handled();
+    }
+
+    pub fn fixed_update(&mut self, c: &mut C) {
+        for ix in 0..self.regions.len() {
+            let blocked = self.blocked(ix);
+            let trans = self.regions[ix].poll_fixed_update(c, &blocked);
+            self.apply(ix, trans, c, Cause::FixedUpdate, None);
+        }
+    }
+
+    pub fn update(&mut self, c: &mut C, duration: &Duration, env: &C::Env) {
+        for ix in 0..self.regions.len() {
+            let blocked = self.blocked(ix);
+            let trans = self.regions[ix].poll_update(c, duration, env, &blocked);
+            self.apply(ix, trans, c, Cause::Update, None);
+        }
+    }
+
+    /// Interrupts one region. Blocks don't apply, cancels do.
+    pub fn interrupt(&mut self, ix: usize, id: C::Id, c: &mut C) {
+        self.regions[ix].interrupt(id, c);
+        self.cancel(ix, id, c);
+    }
+
+    /// States region `ix` may not enter because of what the others run.
+    fn blocked(&self, ix: usize) -> Vec<C::Id> {
+        self.conflicts
+            .iter()
+            .filter_map(|conflict| match *conflict {
+                Conflict::Blocks(by, id) if self.active_elsewhere(ix, by) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn active_elsewhere(&self, ix: usize, id: C::Id) -> bool {
+        self.regions
+            .iter()
+            .enumerate()
+            .any(|(other, r)| other != ix && r.stack().contains(&id))
+    }
+
+    fn apply(
+        &mut self,
+        ix: usize,
+        trans: Trans<C::Id>,
+        c: &mut C,
+        cause: Cause,
+        snapshot: Option<C::Snapshot>,
+    ) {
+        self.regions[ix].transition(trans, c, cause, snapshot);
+        if let Some(id) = trans.target() {
+            self.cancel(ix, id, c);
+        }
+    }
+
+    fn cancel(&mut self, ix: usize, entered: C::Id, c: &mut C) {
+        let cancelled: Vec<C::Id> = self.conflicts
+            .iter()
+            .filter_map(|conflict| match *conflict {
+                Conflict::Cancels(by, id) if by == entered => Some(id),
+                _ => None,
+            })
+            .collect();
+
+        for (other, region) in self.regions.iter_mut().enumerate() {
+            if other != ix && region.stack().iter().any(|id| cancelled.contains(id)) {
+                region.unwind(c, Cause::Conflict);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Pose {
+        Stand,
+        Crouch,
+        Rest,
+        Wave,
+    }
+
+    #[derive(Default)]
+    struct Puppet {
+        crouch: bool,
+        wave: bool,
+        handled: usize,
+        waves: usize,
+    }
+
+    struct Still;
+
+    impl State<Puppet> for Still {}
+
+    impl Controlled for Puppet {
+        type Id = Pose;
+        type Env = ();
+        type Snapshot = (bool, bool);
+
+        fn state(_id: Pose) -> Box<State<Puppet>> {
+            Box::new(Still)
+        }
+
+        fn events_handled(&mut self) {
+            self.crouch = false;
+            self.wave = false;
+            self.handled += 1;
+        }
+
+        fn snapshot(&self) -> (bool, bool) {
+            (self.crouch, self.wave)
+        }
+    }
+
+    fn puppet() -> (Puppet, Regions<Puppet>) {
+        let legs = StateMachine::<Puppet>::new(Pose::Stand).with_rules(vec![
+            Rule::new(
+                "crouch",
+                &[Pose::Stand],
+                |p: &Puppet| p.crouch,
+                Trans::Push(Pose::Crouch),
+            ),
+        ]);
+        let arms = StateMachine::<Puppet>::new(Pose::Rest).with_rules(vec![
+            Rule::new(
+                "wave",
+                &[Pose::Rest],
+                |p: &Puppet| p.wave,
+                Trans::Push(Pose::Wave),
+            ).action(|p: &mut Puppet| p.waves += 1),
+        ]);
+
+        let mut p = Puppet::default();
+        let mut regions = Regions::new(vec![legs, arms]).with_conflicts(vec![
+            Conflict::Blocks(Pose::Crouch, Pose::Wave),
+            Conflict::Cancels(Pose::Crouch, Pose::Wave),
+        ]);
+        regions.start(&mut p);
+        (p, regions)
+    }
+
+    #[test]
+    fn regions_share_the_events() {
+        let (mut p, mut regions) = puppet();
+
+        p.wave = true;
+        regions.handle_events(&mut p);
+        assert_eq!(regions.current(), vec![Some(Pose::Stand), Some(Pose::Wave)]);
+        assert_eq!(p.handled, 1);
+        assert_eq!(p.waves, 1);
+        assert_eq!(regions.visible(), vec![Pose::Stand, Pose::Wave]);
+    }
+
+    #[test]
+    fn entering_cancels_the_other_region() {
+        let (mut p, mut regions) = puppet();
+
+        p.wave = true;
+        regions.handle_events(&mut p);
+        p.crouch = true;
+        regions.handle_events(&mut p);
+
+        assert_eq!(regions.current(), vec![Some(Pose::Crouch), Some(Pose::Rest)]);
+        assert_eq!(
+            regions.region(1).history().back().map(|r| r.cause),
+            Some(Cause::Conflict)
+        );
+    }
+
+    #[test]
+    fn blocked_while_the_other_region_runs() {
+        let (mut p, mut regions) = puppet();
+
+        p.crouch = true;
+        regions.handle_events(&mut p);
+        p.wave = true;
+        regions.handle_events(&mut p);
+
+        assert_eq!(regions.current(), vec![Some(Pose::Crouch), Some(Pose::Rest)]);
+        assert!(!regions.is_active(Pose::Wave));
+    }
+
+    #[test]
+    fn blocked_rules_take_no_action() {
+        let (mut p, mut regions) = puppet();
+
+        p.crouch = true;
+        p.wave = true;
+        regions.handle_events(&mut p);
+
+        assert_eq!(regions.current(), vec![Some(Pose::Crouch), Some(Pose::Rest)]);
+        assert_eq!(p.waves, 0);
+        assert_eq!(p.handled, 1);
+    }
+}
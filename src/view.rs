@@ -0,0 +1,121 @@
+use ggez::{Context, GameResult};
+use ggez::graphics;
+use ggez::graphics::{Canvas, DrawParam, Drawable, Point, Rect};
+
+use camera::*;
+use party::Party;
+use physics::quad_tree;
+use Vector2;
+
+/// A camera rendering to part of the window, following some of the players.
+///
+/// Views draw into a canvas the size of the window and `present` copies
+/// their viewport out of it, so nothing spills into the views next to them.
+pub struct View {
+    pub camera: Camera,
+    pub director: Director,
+    /// Slots followed, none for an overview of the whole level.
+    pub players: Vec<usize>,
+    /// The view with a single player, it only grows to fit more.
+    pub min_size: Vector2,
+    canvas: Canvas,
+}
+
+impl View {
+    /// Room kept between the players and the edges of the view.
+    const MARGIN: f64 = 384.0;
+
+    pub fn new(
+        ctx: &mut Context,
+        viewport: Rect,
+        min_size: Vector2,
+        director: Director,
+        players: Vec<usize>,
+    ) -> GameResult<View> {
+        let (w, h) = (ctx.conf.window_width, ctx.conf.window_height);
+        let mut camera = Camera::new(w, h, min_size.x, min_size.y);
+        camera.set_viewport(viewport);
+
+        Ok(View {
+            camera,
+            director,
+            players,
+            min_size,
+            canvas: Canvas::with_window_size(ctx)?,
+        })
+    }
+
+    pub fn follows(&self, slot: usize) -> bool {
+        self.players.contains(&slot)
+    }
+
+    pub fn update(&mut self, party: &Party, dt: f64, bounds: &quad_tree::Rect) {
+        if self.players.is_empty() {
+            let lo = Vector2::new(bounds.x, bounds.y);
+            let hi = lo + Vector2::new(bounds.w, bounds.h);
+            self.camera.frame(&[lo, hi], Vector2::new(0.0, 0.0), self.min_size);
+            self.camera.update(dt);
+            return;
+        }
+
+        let slots: Vec<_> = self.players
+            .iter()
+            .filter_map(|&ix| party.slots.get(ix))
+            .collect();
+        if slots.is_empty() {
+            return;
+        }
+
+        let positions: Vec<Vector2> = slots.iter().map(|s| s.player.mv.position).collect();
+        let margin = Vector2::new(View::MARGIN, View::MARGIN);
+        self.camera.frame(&positions, margin, self.min_size);
+        self.camera.update(dt);
+
+        // The middle of the players, after `frame` centered on it.
+        let velocity = slots
+            .iter()
+            .fold(Vector2::new(0.0, 0.0), |v, s| v + s.player.mv.velocity);
+        let target = Target {
+            position: self.camera.location(),
+            velocity: velocity / slots.len() as f64,
+            on_ground: slots.iter().all(|s| s.player.mv.on_ground),
+        };
+        self.director.update(&mut self.camera, &target, dt, bounds);
+    }
+
+    /// Sends drawing to the view until `present`.
+    pub fn begin(&self, ctx: &mut Context) {
+        graphics::set_canvas(ctx, Some(&self.canvas));
+        graphics::clear(ctx);
+    }
+
+    /// Copies the view to its viewport in the window.
+    pub fn present(&self, ctx: &mut Context) -> GameResult<()> {
+        let v = self.camera.viewport();
+        let (w, h) = (ctx.conf.window_width as f32, ctx.conf.window_height as f32);
+
+        graphics::set_canvas(ctx, None);
+        self.canvas.draw_ex(
+            ctx,
+            DrawParam {
+                src: Rect::new(v.x / w, v.y / h, v.w / w, v.h / h),
+                dest: Point::new(v.x + v.w / 2.0, v.y + v.h / 2.0),
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// Viewports for `n` views side by side, filling the window.
+pub fn split(width: u32, height: u32, n: usize) -> Vec<Rect> {
+    let w = width as f32 / n.max(1) as f32;
+    (0..n)
+        .map(|ix| Rect::new(ix as f32 * w, 0.0, w, height as f32))
+        .collect()
+}
+
+/// A small viewport in the bottom right corner, for picture in picture.
+pub fn inset(width: u32, height: u32) -> Rect {
+    let (w, h) = (width as f32 / 4.0, height as f32 / 4.0);
+    Rect::new(width as f32 - w - 16.0, height as f32 - h - 16.0, w, h)
+}